@@ -0,0 +1,212 @@
+/// A controllable `Voc` parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Param {
+    Frequency,
+    Tenseness,
+    Intensity,
+    Velum,
+    TongueIndex,
+    TongueDiameter,
+    Lips,
+    Epiglottis,
+    Trachea,
+    /// Voices the glottis above 0.5 and silences it otherwise, fading over
+    /// the attack and release times.
+    Voicing,
+    /// Keeps air flowing while the voice is off above 0.5, as for /h/.
+    Breathing,
+    /// Loudness of the turbulence noise at narrow points of the tract.
+    FricativeIntensity,
+    /// Vibrato depth as a fraction of the frequency.
+    VibratoDepth,
+    /// Vibrato rate in Hz.
+    VibratoRate,
+    /// Target diameter of a single tract segment.
+    Diameter(usize),
+}
+
+/// How a lane travels from the previous breakpoint to the next one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Curve {
+    /// Holds the previous value and jumps at the breakpoint.
+    Step,
+    Linear,
+    /// Constant ratio per sample. Falls back to linear when the two values do
+    /// not share a sign.
+    Exponential,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Breakpoint {
+    /// Position in samples since the voice started rendering.
+    pub time: u64,
+    pub value: f64,
+    /// Shape of the segment leading into this breakpoint.
+    pub curve: Curve,
+}
+
+#[derive(Clone, Debug)]
+pub struct Lane {
+    pub param: Param,
+    points: Vec<Breakpoint>,
+    pub(crate) last: Option<f64>,
+}
+
+impl Lane {
+    fn new(param: Param) -> Self {
+        Lane {
+            param,
+            points: Vec::new(),
+            last: None,
+        }
+    }
+
+    pub fn points(&self) -> &[Breakpoint] {
+        &self.points
+    }
+
+    /// The lane's value at `time`, or `None` before its first breakpoint.
+    pub fn value_at(&self, time: u64) -> Option<f64> {
+        let next = self.points.partition_point(|p| p.time <= time);
+        if next == 0 {
+            return None;
+        }
+
+        let prev = &self.points[next - 1];
+        let Some(target) = self.points.get(next) else {
+            return Some(prev.value);
+        };
+
+        let frac = (time - prev.time) as f64 / (target.time - prev.time) as f64;
+        let value = match target.curve {
+            Curve::Step => prev.value,
+            Curve::Exponential if prev.value * target.value > 0.0 => {
+                prev.value * (target.value / prev.value).powf(frac)
+            }
+            Curve::Linear | Curve::Exponential => prev.value + (target.value - prev.value) * frac,
+        };
+        Some(value)
+    }
+}
+
+/// Timestamped parameter changes that `Voc` applies at the exact sample they
+/// fall on. Parameters without breakpoints are left alone, and a lane only
+/// writes its parameter when its value changes, so setters called between
+/// renders hold until the next breakpoint.
+#[derive(Clone, Debug, Default)]
+pub struct Automation {
+    lanes: Vec<Lane>,
+}
+
+impl Automation {
+    pub fn new() -> Self {
+        Automation { lanes: Vec::new() }
+    }
+
+    /// Jumps `param` to `value` at `time`.
+    pub fn set(&mut self, param: Param, time: u64, value: f64) {
+        self.add(param, time, value, Curve::Step);
+    }
+
+    /// Moves `param` from the previous breakpoint to `value`, reaching it at
+    /// `time`.
+    pub fn ramp(&mut self, param: Param, time: u64, value: f64, curve: Curve) {
+        self.add(param, time, value, curve);
+    }
+
+    pub fn add(&mut self, param: Param, time: u64, value: f64, curve: Curve) {
+        let lane = match self.lanes.iter().position(|l| l.param == param) {
+            Some(i) => &mut self.lanes[i],
+            None => {
+                self.lanes.push(Lane::new(param));
+                self.lanes.last_mut().unwrap()
+            }
+        };
+        let at = lane.points.partition_point(|p| p.time <= time);
+        lane.points.insert(at, Breakpoint { time, value, curve });
+    }
+
+    pub fn lane(&self, param: Param) -> Option<&Lane> {
+        self.lanes.iter().find(|l| l.param == param)
+    }
+
+    /// Takes the lane for `param` out, leaving the parameter unautomated.
+    pub fn remove(&mut self, param: Param) -> Option<Lane> {
+        let i = self.lanes.iter().position(|l| l.param == param)?;
+        Some(self.lanes.remove(i))
+    }
+
+    pub fn lanes(&self) -> &[Lane] {
+        &self.lanes
+    }
+
+    pub(crate) fn lanes_mut(&mut self) -> &mut [Lane] {
+        &mut self.lanes
+    }
+
+    pub fn value_at(&self, param: Param, time: u64) -> Option<f64> {
+        self.lane(param).and_then(|l| l.value_at(time))
+    }
+
+    /// Time of the last breakpoint, if any.
+    pub fn end(&self) -> Option<u64> {
+        self.lanes
+            .iter()
+            .filter_map(|l| l.points.last().map(|p| p.time))
+            .max()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lanes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.lanes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_before_and_after_breakpoints() {
+        let mut a = Automation::new();
+        a.set(Param::Velum, 100, 0.4);
+        assert_eq!(a.value_at(Param::Velum, 99), None);
+        assert_eq!(a.value_at(Param::Velum, 100), Some(0.4));
+        assert_eq!(a.value_at(Param::Velum, 10_000), Some(0.4));
+        assert_eq!(a.value_at(Param::Lips, 100), None);
+    }
+
+    #[test]
+    fn test_curves() {
+        let mut a = Automation::new();
+        a.set(Param::Frequency, 0, 100.0);
+        a.ramp(Param::Frequency, 100, 200.0, Curve::Linear);
+        a.ramp(Param::Frequency, 200, 400.0, Curve::Exponential);
+        a.ramp(Param::Frequency, 300, 100.0, Curve::Step);
+
+        assert_eq!(a.value_at(Param::Frequency, 50), Some(150.0));
+        let mid = a.value_at(Param::Frequency, 150).unwrap();
+        assert!((mid - 200.0 * 2f64.sqrt()).abs() < 1e-9);
+        assert_eq!(a.value_at(Param::Frequency, 299), Some(400.0));
+        assert_eq!(a.value_at(Param::Frequency, 300), Some(100.0));
+        assert_eq!(a.end(), Some(300));
+    }
+
+    #[test]
+    fn test_breakpoints_stay_sorted() {
+        let mut a = Automation::new();
+        a.set(Param::Diameter(3), 200, 2.0);
+        a.set(Param::Diameter(3), 100, 1.0);
+        let times: Vec<u64> = a
+            .lane(Param::Diameter(3))
+            .unwrap()
+            .points()
+            .iter()
+            .map(|p| p.time)
+            .collect();
+        assert_eq!(times, vec![100, 200]);
+    }
+}
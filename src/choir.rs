@@ -0,0 +1,452 @@
+use std::f64::consts::PI;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::config::{ConfigError, VocConfig};
+use crate::phoneme::ArticulatoryTarget;
+use crate::score::midi_to_frequency;
+use crate::voc::Voc;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Action {
+    On { frequency: f64, intensity: f64 },
+    Off,
+}
+
+/// One singer of a `Choir`: a `Voc` with its own detune, tract length and
+/// place in the stereo field.
+pub struct ChoirVoice {
+    voc: Voc,
+    pan: f64,
+    detune: f64,
+    note: Option<f64>,
+    age: u64,
+    // Scattered note changes, in firing order.
+    events: Vec<(u64, Action)>,
+}
+
+impl ChoirVoice {
+    pub fn voc(&self) -> &Voc {
+        &self.voc
+    }
+
+    pub fn voc_mut(&mut self) -> &mut Voc {
+        &mut self.voc
+    }
+
+    /// Position from -1 (left) to 1 (right).
+    pub fn pan(&self) -> f64 {
+        self.pan
+    }
+
+    /// Fixed pitch offset in cents.
+    pub fn detune(&self) -> f64 {
+        self.detune
+    }
+
+    /// The MIDI note the voice is allocated to, if any.
+    pub fn note(&self) -> Option<f64> {
+        self.note
+    }
+
+    fn schedule(&mut self, time: u64, action: Action) {
+        let at = self.events.partition_point(|&(t, _)| t <= time);
+        self.events.insert(at, (time, action));
+    }
+
+    fn fire(&mut self, action: Action) {
+        match action {
+            Action::On {
+                frequency,
+                intensity,
+            } => {
+                self.voc.set_frequency(frequency);
+                self.voc.set_intensity(intensity);
+                self.voc.voice_on();
+            }
+            Action::Off => self.voc.voice_off(),
+        }
+    }
+}
+
+/// A pool of `Voc` voices sharing one set of notes. Each note takes
+/// `voices_per_note` free voices, stealing the longest-held ones when the
+/// pool runs out, and the voices are mixed to stereo.
+pub struct Choir {
+    voices: Vec<ChoirVoice>,
+    voices_per_note: usize,
+    scatter: u64,
+    gain: f64,
+    rng: StdRng,
+    time: u64,
+    buf: Vec<f64>,
+}
+
+impl Choir {
+    pub fn builder() -> ChoirBuilder {
+        ChoirBuilder {
+            config: VocConfig::default(),
+            voices: 4,
+            voices_per_note: 1,
+            detune: 8.0,
+            scatter: 0.03,
+            tract_spread: 0.06,
+            width: 0.8,
+            seed: None,
+        }
+    }
+
+    pub fn voices(&self) -> &[ChoirVoice] {
+        &self.voices
+    }
+
+    pub fn voices_mut(&mut self) -> &mut [ChoirVoice] {
+        &mut self.voices
+    }
+
+    /// Output gain applied to the mix, by default one over the square root
+    /// of the number of voices.
+    pub fn gain(&self) -> f64 {
+        self.gain
+    }
+
+    pub fn set_gain(&mut self, gain: f64) {
+        self.gain = gain;
+    }
+
+    /// Moves every voice to `target`, rescaled to its tract length.
+    pub fn apply_target(&mut self, target: &ArticulatoryTarget) {
+        for voice in &mut self.voices {
+            voice.voc.apply_target(target);
+        }
+    }
+
+    /// Starts MIDI note `pitch` with `velocity` from 0 to 1. Each voice
+    /// enters at its own moment within the timing scatter. A note never
+    /// takes more voices than the choir has.
+    pub fn note_on(&mut self, pitch: f64, velocity: f64) {
+        for _ in 0..self.voices_per_note {
+            let time = self.time;
+            // Voices this note has already taken are not stolen back.
+            let taken = |v: &ChoirVoice| v.note == Some(pitch) && v.age == time;
+            let free = self.voices.iter().position(|v| v.note.is_none());
+            let stolen = || {
+                self.voices
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, v)| !taken(v))
+                    .min_by_key(|(_, v)| v.age)
+                    .map(|(i, _)| i)
+            };
+            let Some(i) = free.or_else(stolen) else {
+                break;
+            };
+            let delay = self.rng.gen_range(0..=self.scatter);
+            let voice = &mut self.voices[i];
+            voice.note = Some(pitch);
+            voice.age = self.time;
+            // A stolen voice drops whatever it still had pending.
+            voice.events.clear();
+            let frequency = midi_to_frequency(pitch + voice.detune / 100.0);
+            voice.schedule(
+                self.time + delay,
+                Action::On {
+                    frequency,
+                    intensity: velocity,
+                },
+            );
+        }
+    }
+
+    pub fn note_off(&mut self, pitch: f64) {
+        for voice in &mut self.voices {
+            if voice.note == Some(pitch) {
+                voice.note = None;
+                let delay = self.rng.gen_range(0..=self.scatter);
+                voice.schedule(self.time + delay, Action::Off);
+            }
+        }
+    }
+
+    /// Renders the mix into `left` and `right`, which must be equally long.
+    /// Longer buffers are rendered a chunk at a time, so this never
+    /// allocates.
+    pub fn process(&mut self, left: &mut [f64], right: &mut [f64]) {
+        assert_eq!(left.len(), right.len(), "stereo buffers differ in length");
+        let chunk = self.buf.len();
+        for (left, right) in left.chunks_mut(chunk).zip(right.chunks_mut(chunk)) {
+            self.process_chunk(left, right);
+        }
+    }
+
+    // Renders at most one chunk.
+    fn process_chunk(&mut self, left: &mut [f64], right: &mut [f64]) {
+        let len = left.len();
+        left.fill(0.0);
+        right.fill(0.0);
+
+        let end = self.time + len as u64;
+        for voice in &mut self.voices {
+            let buf = &mut self.buf[..len];
+            let mut pos = 0;
+            while pos < len {
+                let next = match voice.events.first() {
+                    Some(&(t, action)) if t < end => {
+                        if t <= self.time + pos as u64 {
+                            voice.events.remove(0);
+                            voice.fire(action);
+                            continue;
+                        }
+                        (t - self.time) as usize
+                    }
+                    _ => len,
+                };
+                voice.voc.process(&mut buf[pos..next]);
+                pos = next;
+            }
+
+            // Equal-power panning.
+            let angle = (voice.pan + 1.0) * PI / 4.0;
+            let (l, r) = (angle.cos() * self.gain, angle.sin() * self.gain);
+            for ((x, out_l), out_r) in buf.iter().zip(left.iter_mut()).zip(right.iter_mut()) {
+                *out_l += x * l;
+                *out_r += x * r;
+            }
+        }
+
+        self.time = end;
+    }
+}
+
+pub struct ChoirBuilder {
+    config: VocConfig,
+    voices: usize,
+    voices_per_note: usize,
+    detune: f64,
+    scatter: f64,
+    tract_spread: f64,
+    width: f64,
+    seed: Option<u64>,
+}
+
+impl ChoirBuilder {
+    /// Configuration every voice starts from before its tract is stretched.
+    pub fn config(mut self, config: VocConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn voices(mut self, voices: usize) -> Self {
+        self.voices = voices.max(1);
+        self
+    }
+
+    pub fn voices_per_note(mut self, voices: usize) -> Self {
+        self.voices_per_note = voices.max(1);
+        self
+    }
+
+    /// Largest per-voice pitch offset in cents.
+    pub fn detune(mut self, cents: f64) -> Self {
+        self.detune = cents;
+        self
+    }
+
+    /// Longest delay in seconds before a voice follows a note change.
+    pub fn scatter(mut self, seconds: f64) -> Self {
+        self.scatter = seconds;
+        self
+    }
+
+    /// Largest relative change in tract length, e.g. 0.06 for ±6%.
+    pub fn tract_spread(mut self, spread: f64) -> Self {
+        self.tract_spread = spread;
+        self
+    }
+
+    /// Stereo spread of the voices, from 0 (mono) to 1 (hard left to right).
+    pub fn width(mut self, width: f64) -> Self {
+        self.width = width.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn build(self) -> Result<Choir, ConfigError> {
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        let mut voices = Vec::with_capacity(self.voices);
+        for i in 0..self.voices {
+            let spread = self.tract_spread * rng.gen_range(-1.0..=1.0);
+            let mut config = stretch(&self.config, 1.0 + spread);
+            config.seed = Some(rng.gen());
+            let mut voc = Voc::from_config(config)?;
+            voc.silence();
+
+            let pan = if self.voices > 1 {
+                self.width * (2.0 * i as f64 / (self.voices - 1) as f64 - 1.0)
+            } else {
+                0.0
+            };
+            voices.push(ChoirVoice {
+                voc,
+                pan,
+                detune: self.detune * rng.gen_range(-1.0..=1.0),
+                note: None,
+                age: 0,
+                events: Vec::new(),
+            });
+        }
+
+        Ok(Choir {
+            voices,
+            voices_per_note: self.voices_per_note,
+            scatter: (self.scatter.max(0.0) * self.config.samplerate) as u64,
+            gain: 1.0 / (self.voices as f64).sqrt(),
+            rng,
+            time: 0,
+            buf: vec![0.0; self.config.chunk],
+        })
+    }
+}
+
+// Scales the tract and every landmark along it by `factor`.
+fn stretch(config: &VocConfig, factor: f64) -> VocConfig {
+    let scale = |x: usize| (x as f64 * factor).round() as usize;
+    VocConfig {
+        n: scale(config.n),
+        nose_length: scale(config.nose_length),
+        nose_start: scale(config.nose_start),
+        tip_start: scale(config.tip_start),
+        blade_start: scale(config.blade_start),
+        epiglottis_start: scale(config.epiglottis_start),
+        lip_start: scale(config.lip_start),
+        ..config.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn energy(x: &[f64]) -> f64 {
+        x.iter().map(|v| v * v).sum()
+    }
+
+    #[test]
+    fn test_allocation_and_stealing() {
+        let mut choir = Choir::builder()
+            .voices(3)
+            .voices_per_note(2)
+            .seed(1)
+            .build()
+            .unwrap();
+        let notes =
+            |c: &Choir| -> Vec<Option<f64>> { c.voices().iter().map(|v| v.note()).collect() };
+
+        choir.note_on(60.0, 1.0);
+        assert_eq!(notes(&choir), vec![Some(60.0), Some(60.0), None]);
+
+        let (mut l, mut r) = (vec![0.0; 64], vec![0.0; 64]);
+        choir.process(&mut l, &mut r);
+        choir.note_on(64.0, 1.0);
+        assert_eq!(notes(&choir), vec![Some(64.0), Some(60.0), Some(64.0)]);
+
+        choir.note_off(64.0);
+        assert_eq!(notes(&choir), vec![None, Some(60.0), None]);
+    }
+
+    #[test]
+    fn test_note_keeps_the_voices_it_takes() {
+        let notes =
+            |c: &Choir| -> Vec<Option<f64>> { c.voices().iter().map(|v| v.note()).collect() };
+
+        // Every voice is equally old at the start, so stealing must skip the
+        // voices the note has just taken.
+        let mut choir = Choir::builder()
+            .voices(2)
+            .voices_per_note(2)
+            .seed(1)
+            .build()
+            .unwrap();
+        choir.note_on(60.0, 1.0);
+        choir.note_on(64.0, 1.0);
+        assert_eq!(notes(&choir), vec![Some(64.0), Some(64.0)]);
+
+        let mut choir = Choir::builder()
+            .voices(2)
+            .voices_per_note(3)
+            .seed(1)
+            .build()
+            .unwrap();
+        choir.note_on(60.0, 1.0);
+        assert_eq!(notes(&choir), vec![Some(60.0), Some(60.0)]);
+        assert!(choir.voices().iter().all(|v| v.events.len() == 1));
+    }
+
+    #[test]
+    fn test_long_buffers_render_in_chunks() {
+        let build = || Choir::builder().voices(2).seed(4).build().unwrap();
+        let mut a = build();
+        let mut b = build();
+        a.note_on(60.0, 1.0);
+        b.note_on(60.0, 1.0);
+
+        let chunk = a.buf.len();
+        let (mut l, mut r) = (vec![0.0; 5 * chunk + 7], vec![0.0; 5 * chunk + 7]);
+        a.process(&mut l, &mut r);
+        assert_eq!(a.buf.len(), chunk);
+
+        let (mut l2, mut r2) = (vec![0.0; l.len()], vec![0.0; l.len()]);
+        for (l, r) in l2.chunks_mut(100).zip(r2.chunks_mut(100)) {
+            b.process(l, r);
+        }
+        assert_eq!(l, l2);
+        assert_eq!(r, r2);
+    }
+
+    #[test]
+    fn test_voices_differ() {
+        let choir = Choir::builder().voices(6).seed(2).build().unwrap();
+        let lengths: Vec<usize> = choir
+            .voices()
+            .iter()
+            .map(|v| v.voc().tract_size())
+            .collect();
+        assert!(lengths.iter().any(|&n| n != lengths[0]), "{:?}", lengths);
+        assert!(choir.voices().iter().all(|v| v.detune().abs() <= 8.0));
+        assert_eq!(choir.voices()[0].pan(), -0.8);
+        assert_eq!(choir.voices()[5].pan(), 0.8);
+    }
+
+    #[test]
+    fn test_idle_voices_start_silent() {
+        let mut choir = Choir::builder().seed(5).build().unwrap();
+        let (mut l, mut r) = (vec![1.0; 1024], vec![1.0; 1024]);
+        choir.process(&mut l, &mut r);
+        assert!(l.iter().chain(&r).all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn test_panning() {
+        let mut choir = Choir::builder()
+            .voices(2)
+            .width(1.0)
+            .scatter(0.0)
+            .seed(3)
+            .build()
+            .unwrap();
+        let (mut l, mut r) = (vec![0.0; 22050], vec![0.0; 22050]);
+        choir.note_on(57.0, 1.0);
+        choir.process(&mut l, &mut r);
+        assert!(energy(&l) > 1e-3);
+        assert!(energy(&r) < energy(&l) * 1e-6);
+    }
+}
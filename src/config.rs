@@ -0,0 +1,255 @@
+use std::error::Error;
+use std::fmt;
+
+/// Construction parameters for `Voc`. The defaults reproduce the original Pink
+/// Trombone: a 44-segment tract with a 28-segment nose.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VocConfig {
+    pub samplerate: f64,
+    pub chunk: usize,
+    pub vocal_output_scaler: f64,
+    pub frequency: f64,
+    pub tenseness: f64,
+    pub n: usize,
+    pub nose_length: usize,
+    pub nose_start: usize,
+    pub tip_start: usize,
+    pub blade_start: usize,
+    pub epiglottis_start: usize,
+    pub lip_start: usize,
+    pub seed: Option<u64>,
+}
+
+impl Default for VocConfig {
+    fn default() -> Self {
+        VocConfig {
+            samplerate: 44100.0,
+            chunk: 512,
+            vocal_output_scaler: 0.125,
+            frequency: 400.0,
+            tenseness: 0.6,
+            n: 44,
+            nose_length: 28,
+            nose_start: 17,
+            tip_start: 32,
+            blade_start: 12,
+            epiglottis_start: 6,
+            lip_start: 39,
+            seed: None,
+        }
+    }
+}
+
+impl VocConfig {
+    pub fn builder() -> VocConfigBuilder {
+        VocConfigBuilder {
+            config: VocConfig::default(),
+        }
+    }
+
+    /// Checks that the parameters describe a tract `Voc` can simulate.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(self.samplerate.is_finite() && self.samplerate > 0.0) {
+            return Err(ConfigError::InvalidSampleRate(self.samplerate));
+        }
+        if self.chunk == 0 {
+            return Err(ConfigError::ZeroChunk);
+        }
+        if !(self.frequency.is_finite() && self.frequency > 0.0) {
+            return Err(ConfigError::InvalidFrequency(self.frequency));
+        }
+        if !(0.0..=1.0).contains(&self.tenseness) {
+            return Err(ConfigError::InvalidTenseness(self.tenseness));
+        }
+        if self.nose_length < 2 {
+            return Err(ConfigError::NoseTooShort(self.nose_length));
+        }
+        if self.nose_start == 0 || self.nose_start + 1 >= self.n {
+            return Err(ConfigError::NoseStartOutOfRange {
+                nose_start: self.nose_start,
+                n: self.n,
+            });
+        }
+
+        if self.epiglottis_start == 0 {
+            return Err(ConfigError::Misordered {
+                first: "0",
+                second: "epiglottis_start",
+            });
+        }
+        let order = [
+            ("epiglottis_start", self.epiglottis_start),
+            ("blade_start", self.blade_start),
+            ("tip_start", self.tip_start),
+            ("lip_start", self.lip_start),
+            ("n", self.n),
+        ];
+        for pair in order.windows(2) {
+            if pair[0].1 >= pair[1].1 {
+                return Err(ConfigError::Misordered {
+                    first: pair[0].0,
+                    second: pair[1].0,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub struct VocConfigBuilder {
+    config: VocConfig,
+}
+
+impl VocConfigBuilder {
+    pub fn samplerate(mut self, samplerate: f64) -> Self {
+        self.config.samplerate = samplerate;
+        self
+    }
+
+    pub fn chunk(mut self, chunk: usize) -> Self {
+        self.config.chunk = chunk;
+        self
+    }
+
+    pub fn vocal_output_scaler(mut self, scaler: f64) -> Self {
+        self.config.vocal_output_scaler = scaler;
+        self
+    }
+
+    pub fn frequency(mut self, frequency: f64) -> Self {
+        self.config.frequency = frequency;
+        self
+    }
+
+    pub fn tenseness(mut self, tenseness: f64) -> Self {
+        self.config.tenseness = tenseness;
+        self
+    }
+
+    pub fn n(mut self, n: usize) -> Self {
+        self.config.n = n;
+        self
+    }
+
+    pub fn nose_length(mut self, nose_length: usize) -> Self {
+        self.config.nose_length = nose_length;
+        self
+    }
+
+    pub fn nose_start(mut self, nose_start: usize) -> Self {
+        self.config.nose_start = nose_start;
+        self
+    }
+
+    pub fn tip_start(mut self, tip_start: usize) -> Self {
+        self.config.tip_start = tip_start;
+        self
+    }
+
+    pub fn blade_start(mut self, blade_start: usize) -> Self {
+        self.config.blade_start = blade_start;
+        self
+    }
+
+    pub fn epiglottis_start(mut self, epiglottis_start: usize) -> Self {
+        self.config.epiglottis_start = epiglottis_start;
+        self
+    }
+
+    pub fn lip_start(mut self, lip_start: usize) -> Self {
+        self.config.lip_start = lip_start;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = Some(seed);
+        self
+    }
+
+    pub fn build(self) -> Result<VocConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    InvalidSampleRate(f64),
+    ZeroChunk,
+    InvalidFrequency(f64),
+    InvalidTenseness(f64),
+    NoseTooShort(usize),
+    NoseStartOutOfRange {
+        nose_start: usize,
+        n: usize,
+    },
+    Misordered {
+        first: &'static str,
+        second: &'static str,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::InvalidSampleRate(sr) => {
+                write!(f, "sample rate must be positive, got {}", sr)
+            }
+            ConfigError::ZeroChunk => write!(f, "chunk size must be at least 1"),
+            ConfigError::InvalidFrequency(freq) => {
+                write!(f, "frequency must be positive, got {}", freq)
+            }
+            ConfigError::InvalidTenseness(t) => {
+                write!(f, "tenseness must be within 0..=1, got {}", t)
+            }
+            ConfigError::NoseTooShort(len) => {
+                write!(f, "nose needs at least 2 segments, got {}", len)
+            }
+            ConfigError::NoseStartOutOfRange { nose_start, n } => write!(
+                f,
+                "nose_start must be within 1..{} for a tract of {} segments, got {}",
+                n.saturating_sub(1),
+                n,
+                nose_start
+            ),
+            ConfigError::Misordered { first, second } => {
+                write!(f, "{} must be less than {}", first, second)
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_valid() {
+        assert_eq!(VocConfig::builder().build(), Ok(VocConfig::default()));
+    }
+
+    #[test]
+    fn test_rejects_bad_geometry() {
+        assert_eq!(
+            VocConfig::builder().nose_start(43).build(),
+            Err(ConfigError::NoseStartOutOfRange {
+                nose_start: 43,
+                n: 44
+            })
+        );
+        assert_eq!(
+            VocConfig::builder().tip_start(12).build(),
+            Err(ConfigError::Misordered {
+                first: "blade_start",
+                second: "tip_start"
+            })
+        );
+        assert_eq!(
+            VocConfig::builder().chunk(0).build(),
+            Err(ConfigError::ZeroChunk)
+        );
+    }
+}
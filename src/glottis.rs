@@ -1,19 +1,68 @@
 use rand::Rng;
 use std::f64::consts::PI;
 
+use crate::noise::{BandpassFilter, SmoothNoise};
+use crate::source::{GlottalSource, LfModel};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PitchMarkKind {
+    PeriodStart,
+    GlottalClosure,
+}
+
+/// A glottal event, `index` samples into the rendered block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PitchMark {
+    pub index: usize,
+    pub kind: PitchMarkKind,
+}
+
+fn lerp(old: f64, new: f64, lambda: f64) -> f64 {
+    old * (1.0 - lambda) + new * lambda
+}
+
+/// The voice source. Always `f64`, whatever the sample type of the `Voc`.
+#[derive(Clone)]
 pub struct Glottis {
     pub freq: f64,
     pub tenseness: f64,
-    rd: f64,
+    pub intensity: f64,
+    pub voiced: bool,
+    /// Keeps air flowing while the voice is off, so aspiration still sounds,
+    /// as for /h/.
+    pub breathing: bool,
+    pub attack: f64,
+    pub release: f64,
+    voicing: f64,
+    airflow: f64,
+    old_freq: f64,
+    new_freq: f64,
+    old_tenseness: f64,
+    new_tenseness: f64,
+    old_intensity: f64,
+    new_intensity: f64,
+    cur_tenseness: f64,
+    cur_intensity: f64,
+    started: bool,
+    /// Vibrato depth as a fraction of the frequency. Off by default.
+    pub vibrato_amount: f64,
+    pub vibrato_frequency: f64,
+    /// Scale of the slow random pitch drift. Off by default.
+    pub wobble: f64,
+    pub jitter: f64,
+    pub shimmer: f64,
+    amplitude: f64,
+    aspiration_filter: BandpassFilter,
+    aspiration_level: SmoothNoise,
+    wobble_fast: SmoothNoise,
+    wobble_slow: SmoothNoise,
+    total_time: f64,
+    source: Box<dyn GlottalSource>,
     waveform_length: f64,
     time_in_waveform: f64,
-    alpha: f64,
-    e0: f64,
-    epsilon: f64,
-    shift: f64,
-    delta: f64,
-    te: f64,
-    omega: f64,
+    phase: f64,
+    period_started: bool,
+    closed: bool,
     t: f64,
     pub sr: f64,
 }
@@ -23,84 +72,374 @@ impl Glottis {
         let mut glottis = Glottis {
             freq: default_freq,
             tenseness: default_tenseness,
-            rd: 0.0,
+            intensity: 1.0,
+            voiced: true,
+            breathing: false,
+            attack: 0.05,
+            release: 0.15,
+            voicing: 1.0,
+            airflow: 1.0,
+            old_freq: default_freq,
+            new_freq: default_freq,
+            old_tenseness: default_tenseness,
+            new_tenseness: default_tenseness,
+            old_intensity: 1.0,
+            new_intensity: 1.0,
+            cur_tenseness: default_tenseness,
+            cur_intensity: 1.0,
+            started: false,
+            vibrato_amount: 0.0,
+            vibrato_frequency: 6.0,
+            wobble: 0.0,
+            jitter: 0.0,
+            shimmer: 0.0,
+            amplitude: 1.0,
+            aspiration_filter: BandpassFilter::new(sr, 500.0, 0.5),
+            aspiration_level: SmoothNoise::new(1.99),
+            wobble_fast: SmoothNoise::new(4.07),
+            wobble_slow: SmoothNoise::new(2.15),
+            total_time: 0.0,
+            source: Box::new(LfModel::new()),
             waveform_length: 0.0,
             time_in_waveform: 0.0,
-            alpha: 0.0,
-            e0: 0.0,
-            epsilon: 0.0,
-            shift: 0.0,
-            delta: 0.0,
-            te: 0.0,
-            omega: 0.0,
+            phase: 0.0,
+            period_started: false,
+            closed: false,
             t: 1.0 / sr,
             sr,
         };
-        glottis.setup_waveform(0.0);
+        glottis.waveform_length = 1.0 / default_freq;
+        glottis.source.setup(default_tenseness);
         glottis
     }
 
-    pub fn setup_waveform(&mut self, _lambda: f64) {
-        self.rd = 3.0 * (1.0 - self.tenseness);
-        self.waveform_length = 1.0 / self.freq;
-
-        let mut rd = self.rd;
-        if rd < 0.5 {
-            rd = 0.5;
-        }
-        if rd > 2.7 {
-            rd = 2.7;
+    /// Starts a new control block: the targets reached at the end of the
+    /// previous block become the start points, and the current `freq`,
+    /// `tenseness` and intensity become the new end points.
+    pub fn begin_block(&mut self) {
+        // First block: start from the targets instead of gliding from the
+        // construction defaults.
+        if !self.started {
+            self.new_freq = self.freq;
+            self.new_tenseness = self.tenseness;
+            self.new_intensity = self.intensity;
+            self.started = true;
         }
+        self.old_freq = self.new_freq;
+        self.old_tenseness = self.new_tenseness;
+        self.old_intensity = self.new_intensity;
+        self.new_freq = self.freq;
+        self.new_tenseness = self.tenseness;
+        self.new_intensity = self.intensity;
+    }
 
-        let ra = -0.01 + 0.048 * rd;
-        let rk = 0.224 + 0.118 * rd;
-        let rg = (rk / 4.0) * (0.5 + 1.2 * rk) / (0.11 * rd - ra * (0.5 + 1.2 * rk));
+    /// Turns the voice and the breath off at once, skipping the release.
+    pub fn silence(&mut self) {
+        self.voiced = false;
+        self.breathing = false;
+        self.voicing = 0.0;
+        self.airflow = 0.0;
+    }
 
-        let ta = ra;
-        let tp = 1.0 / (2.0 * rg);
-        let te = tp + tp * rk;
+    /// Moves to `freq` at once instead of gliding there over the block.
+    pub fn jump_freq(&mut self, freq: f64) {
+        self.freq = freq;
+        self.old_freq = freq;
+        self.new_freq = freq;
+    }
 
-        self.epsilon = 1.0 / ta;
-        self.shift = (-self.epsilon * (1.0 - te)).exp();
-        self.delta = 1.0 - self.shift;
+    pub fn jump_tenseness(&mut self, tenseness: f64) {
+        self.tenseness = tenseness;
+        self.old_tenseness = tenseness;
+        self.new_tenseness = tenseness;
+    }
 
-        let mut rhs_integral = (1.0 / self.epsilon) * (self.shift - 1.0) + (1.0 - te) * self.shift;
-        rhs_integral = rhs_integral / self.delta;
-        let lower_integral = -(te - tp) / 2.0 + rhs_integral;
-        let upper_integral = -lower_integral;
+    pub fn jump_intensity(&mut self, intensity: f64) {
+        self.intensity = intensity;
+        self.old_intensity = intensity;
+        self.new_intensity = intensity;
+    }
 
-        self.omega = PI / tp;
-        let s = (self.omega * te).sin();
+    pub fn setup_waveform<R: Rng + ?Sized>(&mut self, rng: &mut R, lambda: f64) {
+        let vibrato = self.vibrato(rng);
+        let freq = lerp(self.old_freq, self.new_freq, lambda);
+        let tenseness = lerp(self.old_tenseness, self.new_tenseness, lambda);
+        self.waveform_length = 1.0 / (freq * (1.0 + vibrato));
+        self.source.setup(tenseness);
 
-        let y = -PI * s * upper_integral / (tp * 2.0);
-        let z = y.ln();
-        self.alpha = z / (tp / 2.0 - te);
-        self.e0 = -1.0 / (s * (self.alpha * te).exp());
+        if self.jitter > 0.0 {
+            self.waveform_length *= 1.0 + self.jitter * rng.gen_range(-1.0..1.0);
+        }
+        self.amplitude = if self.shimmer > 0.0 {
+            (1.0 + self.shimmer * rng.gen_range(-1.0..1.0)).max(0.0)
+        } else {
+            1.0
+        };
+    }
 
-        self.te = te;
+    /// Replaces the pulse model. The new source takes over immediately.
+    pub fn set_source(&mut self, mut source: Box<dyn GlottalSource>) {
+        source.setup(self.cur_tenseness);
+        self.source = source;
     }
 
-    pub fn compute(&mut self, lambda: f64) -> f64 {
-        let intensity = 1.0;
+    // Relative pitch deviation at the current time: periodic vibrato plus a
+    // slow random drift.
+    fn vibrato<R: Rng + ?Sized>(&mut self, rng: &mut R) -> f64 {
+        let mut vibrato =
+            self.vibrato_amount * (2.0 * PI * self.total_time * self.vibrato_frequency).sin();
+        vibrato += self.wobble * 0.02 * self.wobble_fast.at(rng, self.total_time);
+        vibrato += self.wobble * 0.04 * self.wobble_slow.at(rng, self.total_time);
+        vibrato
+    }
 
+    pub fn compute<R: Rng + ?Sized>(&mut self, rng: &mut R, lambda: f64) -> f64 {
         self.time_in_waveform += self.t;
+        self.total_time += self.t;
 
-        if self.time_in_waveform > self.waveform_length {
+        let wrapped = self.time_in_waveform > self.waveform_length;
+        if wrapped {
             self.time_in_waveform -= self.waveform_length;
-            self.setup_waveform(lambda);
+            self.setup_waveform(rng, lambda);
+            self.phase = 0.0;
         }
 
+        self.update_voicing();
+        self.cur_tenseness = lerp(self.old_tenseness, self.new_tenseness, lambda);
+        self.cur_intensity = lerp(self.old_intensity, self.new_intensity, lambda) * self.voicing;
+
         let t = self.time_in_waveform / self.waveform_length;
 
-        let out = if t > self.te {
-            (-(-self.epsilon * (t - self.te)).exp() + self.shift) / self.delta
+        let closure = self.source.closure_instant();
+        let sounding = self.cur_intensity > 0.0;
+        self.period_started = wrapped && sounding;
+        self.closed = self.phase < closure && t >= closure && sounding;
+        self.phase = t;
+
+        let out = self.source.compute(t) * self.cur_intensity * self.amplitude;
+
+        let noise = self.aspiration_filter.process(rng.gen_range(-1.0..1.0));
+        let breath = lerp(self.old_intensity, self.new_intensity, lambda) * self.airflow;
+        let aspiration = breath
+            * (1.0 - self.cur_tenseness.sqrt())
+            * self.noise_modulator()
+            * noise
+            * (0.2 + 0.02 * self.aspiration_level.at(rng, self.total_time));
+
+        out + aspiration
+    }
+
+    /// Whether the last computed sample began a new glottal period.
+    pub fn period_started(&self) -> bool {
+        self.period_started
+    }
+
+    /// Whether the last computed sample passed the glottal closure instant.
+    pub fn closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Sets the band of the aspiration noise.
+    pub fn set_aspiration_filter(&mut self, center: f64, q: f64) {
+        self.aspiration_filter.set(self.sr, center, q);
+    }
+
+    // Linear voice and airflow on/off ramps, `attack`/`release` seconds from
+    // end to end. Air flows whenever the voice is on.
+    fn update_voicing(&mut self) {
+        self.voicing = self.ramp(self.voicing, self.voiced);
+        self.airflow = self.ramp(self.airflow, self.voiced || self.breathing);
+    }
+
+    fn ramp(&self, level: f64, on: bool) -> f64 {
+        if on {
+            let step = if self.attack > 0.0 {
+                self.t / self.attack
+            } else {
+                1.0
+            };
+            (level + step).min(1.0)
         } else {
-            self.e0 * (self.alpha * t).exp() * (self.omega * t).sin()
+            let step = if self.release > 0.0 {
+                self.t / self.release
+            } else {
+                1.0
+            };
+            (level - step).max(0.0)
+        }
+    }
+
+    /// Airflow envelope for noise sources: follows the glottal opening while
+    /// voiced, falls back to a steady breath when the voice is off and dies
+    /// away once the breath stops.
+    pub fn noise_modulator(&self) -> f64 {
+        let voiced = 0.1
+            + 0.2
+                * (2.0 * PI * self.time_in_waveform / self.waveform_length)
+                    .sin()
+                    .max(0.0);
+        let tenseness_intensity = self.cur_tenseness * self.cur_intensity;
+        (tenseness_intensity * voiced + (1.0 - tenseness_intensity) * 0.3) * self.airflow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Relative pitch deviation of each period started in `seconds`.
+    fn period_deviations(g: &mut Glottis, seconds: f64) -> Vec<f64> {
+        let mut rng = rand::thread_rng();
+        let mut deviations = Vec::new();
+        for _ in 0..(seconds * g.sr) as usize {
+            let before = g.time_in_waveform;
+            g.compute(&mut rng, 0.0);
+            if g.time_in_waveform < before {
+                deviations.push(1.0 / (g.waveform_length * g.freq) - 1.0);
+            }
+        }
+        deviations
+    }
+
+    #[test]
+    fn test_defaults_hold_the_pitch() {
+        let mut g = Glottis::new(44100.0, 100.0, 0.6);
+        let deviations = period_deviations(&mut g, 1.0);
+        assert!(deviations.len() > 90);
+        assert!(deviations.iter().all(|d| d.abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_vibrato_modulates_periods() {
+        let mut g = Glottis::new(44100.0, 100.0, 0.6);
+        g.vibrato_amount = 0.05;
+        g.vibrato_frequency = 5.0;
+        let deviations = period_deviations(&mut g, 2.0);
+
+        assert!(deviations.iter().all(|d| d.abs() <= 0.05 + 1e-9));
+        assert!(deviations.iter().any(|&d| d > 0.045));
+        assert!(deviations.iter().any(|&d| d < -0.045));
+        // Two zero crossings per vibrato cycle.
+        let crossings = deviations.windows(2).filter(|w| w[0] * w[1] < 0.0).count();
+        assert!((19..=21).contains(&crossings), "{}", crossings);
+    }
+
+    #[test]
+    fn test_wobble_drifts_slowly() {
+        let mut g = Glottis::new(44100.0, 100.0, 0.6);
+        g.wobble = 1.0;
+        let deviations = period_deviations(&mut g, 2.0);
+
+        assert!(deviations.iter().all(|d| d.abs() <= 0.06 + 1e-9));
+        assert!(deviations.iter().any(|d| d.abs() > 0.005));
+        assert!(deviations.windows(2).all(|w| (w[1] - w[0]).abs() < 0.01));
+    }
+
+    #[test]
+    fn test_voice_off_releases_to_silence() {
+        let mut g = Glottis::new(44100.0, 100.0, 1.0);
+        g.release = 0.1;
+        g.begin_block();
+        g.voiced = false;
+
+        let mut rng = rand::thread_rng();
+        let mut peak = |g: &mut Glottis, n: usize| {
+            (0..n)
+                .map(|_| g.compute(&mut rng, 0.0).abs())
+                .fold(0.0, f64::max)
         };
+        assert!(peak(&mut g, 2205) > 0.1);
+        peak(&mut g, 4410);
+        assert_eq!(peak(&mut g, 441), 0.0);
 
-        let noise: f64 = rand::thread_rng().gen_range(-1.0..1.0);
-        let aspiration = intensity * (1.0 - self.tenseness.sqrt()) * 0.3 * noise;
+        g.voiced = true;
+        g.attack = 0.0;
+        assert!(peak(&mut g, 441) > 0.1);
+    }
+
+    #[test]
+    fn test_breath_flows_while_voice_is_off() {
+        let mut rng = rand::thread_rng();
+        let mut g = Glottis::new(44100.0, 100.0, 0.4);
+        g.release = 0.0;
+        g.begin_block();
+        g.voiced = false;
+        g.breathing = true;
+
+        let energy: f64 = (0..4410).map(|_| g.compute(&mut rng, 0.0).powi(2)).sum();
+        assert!(energy > 0.0);
+        assert!(g.noise_modulator() > 0.0);
+
+        g.breathing = false;
+        assert!((0..441).all(|_| g.compute(&mut rng, 0.0) == 0.0));
+        assert_eq!(g.noise_modulator(), 0.0);
+    }
+
+    #[test]
+    fn test_jitter_perturbs_periods() {
+        let mut rng = rand::thread_rng();
+        let mut g = Glottis::new(44100.0, 100.0, 0.6);
+        g.vibrato_amount = 0.0;
+        g.wobble = 0.0;
+        g.jitter = 0.02;
+
+        let mut lengths = Vec::new();
+        for _ in 0..44100 {
+            let before = g.waveform_length;
+            g.compute(&mut rng, 0.0);
+            if g.waveform_length != before {
+                lengths.push(g.waveform_length * 100.0);
+            }
+        }
+
+        assert!(lengths.iter().all(|l| (l - 1.0).abs() <= 0.02));
+        assert!(lengths.iter().any(|l| (l - 1.0).abs() > 0.005));
+    }
+
+    #[test]
+    fn test_shimmer_perturbs_amplitudes() {
+        let mut rng = rand::thread_rng();
+        let mut g = Glottis::new(44100.0, 100.0, 0.6);
+        g.shimmer = 0.1;
+
+        let mut amplitudes = Vec::new();
+        for _ in 0..44100 {
+            let before = g.time_in_waveform;
+            g.compute(&mut rng, 0.0);
+            if g.time_in_waveform < before {
+                amplitudes.push(g.amplitude);
+            }
+        }
+
+        assert!(amplitudes.len() > 90);
+        assert!(amplitudes.iter().all(|a| (a - 1.0).abs() <= 0.1));
+        assert!(amplitudes.iter().any(|a| (a - 1.0).abs() > 0.025));
+    }
+
+    #[test]
+    fn test_frequency_glides_across_block() {
+        let mut rng = rand::thread_rng();
+        let mut g = Glottis::new(44100.0, 100.0, 0.6);
+        g.vibrato_amount = 0.0;
+        g.wobble = 0.0;
+        g.begin_block();
+        g.compute(&mut rng, 0.0);
+        g.freq = 200.0;
+        g.begin_block();
+
+        let block = 44100;
+        let mut lengths = Vec::new();
+        for i in 0..block {
+            let before = g.waveform_length;
+            g.compute(&mut rng, i as f64 / block as f64);
+            if g.waveform_length != before {
+                lengths.push(g.waveform_length);
+            }
+        }
 
-        out + aspiration * 0.2
+        assert!(lengths.len() > 100);
+        assert!(lengths.windows(2).all(|w| w[1] < w[0]));
+        assert!(lengths[0] < 1.0 / 100.0 && lengths[0] > 1.0 / 110.0);
+        assert!(*lengths.last().unwrap() > 1.0 / 200.0);
     }
 }
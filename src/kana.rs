@@ -0,0 +1,464 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::phoneme::{Manner, PhonemeInventory};
+use crate::sequence::Sequence;
+
+/// One mora of Japanese text, spelled with symbols of the IPA inventory.
+/// The moraic nasal and the first half of a geminate carry only a
+/// consonant, a long vowel mark only a vowel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mora {
+    pub kana: String,
+    pub consonant: Vec<&'static str>,
+    pub vowel: Option<&'static str>,
+}
+
+impl Mora {
+    pub fn phonemes(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.consonant.iter().copied().chain(self.vowel)
+    }
+
+    fn is_pause(&self) -> bool {
+        self.consonant == ["_"]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum KanaError {
+    UnknownCharacter(char),
+    /// A small kana, sokuon or long vowel mark with nothing to attach to.
+    Misplaced(char),
+    MissingPhoneme(&'static str),
+}
+
+impl fmt::Display for KanaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KanaError::UnknownCharacter(c) => write!(f, "unknown character {:?}", c),
+            KanaError::Misplaced(c) => write!(f, "{:?} does not follow a mora", c),
+            KanaError::MissingPhoneme(s) => write!(f, "phoneme {:?} is not in the inventory", s),
+        }
+    }
+}
+
+impl Error for KanaError {}
+
+// Consonant and vowel of a plain hiragana syllable.
+fn syllable(c: char) -> Option<(&'static [&'static str], &'static str)> {
+    let s: (&[&str], &str) = match c {
+        'あ' => (&[], "a"),
+        'い' | 'ゐ' => (&[], "i"),
+        'う' => (&[], "ɯ"),
+        'え' | 'ゑ' => (&[], "e"),
+        'お' | 'を' => (&[], "o"),
+        'か' => (&["k"], "a"),
+        'き' => (&["k"], "i"),
+        'く' => (&["k"], "ɯ"),
+        'け' => (&["k"], "e"),
+        'こ' => (&["k"], "o"),
+        'が' => (&["g"], "a"),
+        'ぎ' => (&["g"], "i"),
+        'ぐ' => (&["g"], "ɯ"),
+        'げ' => (&["g"], "e"),
+        'ご' => (&["g"], "o"),
+        'さ' => (&["s"], "a"),
+        'し' => (&["ɕ"], "i"),
+        'す' => (&["s"], "ɯ"),
+        'せ' => (&["s"], "e"),
+        'そ' => (&["s"], "o"),
+        'ざ' => (&["z"], "a"),
+        'じ' | 'ぢ' => (&["ʑ"], "i"),
+        'ず' | 'づ' => (&["z"], "ɯ"),
+        'ぜ' => (&["z"], "e"),
+        'ぞ' => (&["z"], "o"),
+        'た' => (&["t"], "a"),
+        'ち' => (&["t", "ɕ"], "i"),
+        'つ' => (&["t", "s"], "ɯ"),
+        'て' => (&["t"], "e"),
+        'と' => (&["t"], "o"),
+        'だ' => (&["d"], "a"),
+        'で' => (&["d"], "e"),
+        'ど' => (&["d"], "o"),
+        'な' => (&["n"], "a"),
+        'に' => (&["ɲ"], "i"),
+        'ぬ' => (&["n"], "ɯ"),
+        'ね' => (&["n"], "e"),
+        'の' => (&["n"], "o"),
+        'は' => (&["h"], "a"),
+        'ひ' => (&["ç"], "i"),
+        'ふ' => (&["ɸ"], "ɯ"),
+        'へ' => (&["h"], "e"),
+        'ほ' => (&["h"], "o"),
+        'ば' => (&["b"], "a"),
+        'び' => (&["b"], "i"),
+        'ぶ' => (&["b"], "ɯ"),
+        'べ' => (&["b"], "e"),
+        'ぼ' => (&["b"], "o"),
+        'ぱ' => (&["p"], "a"),
+        'ぴ' => (&["p"], "i"),
+        'ぷ' => (&["p"], "ɯ"),
+        'ぺ' => (&["p"], "e"),
+        'ぽ' => (&["p"], "o"),
+        'ま' => (&["m"], "a"),
+        'み' => (&["m"], "i"),
+        'む' => (&["m"], "ɯ"),
+        'め' => (&["m"], "e"),
+        'も' => (&["m"], "o"),
+        'や' => (&["j"], "a"),
+        'ゆ' => (&["j"], "ɯ"),
+        'よ' => (&["j"], "o"),
+        'ら' => (&["ɾ"], "a"),
+        'り' => (&["ɾ"], "i"),
+        'る' => (&["ɾ"], "ɯ"),
+        'れ' => (&["ɾ"], "e"),
+        'ろ' => (&["ɾ"], "o"),
+        'わ' => (&["w"], "a"),
+        'ゔ' => (&["β"], "ɯ"),
+        'ゕ' => (&["k"], "a"),
+        'ゖ' => (&["k"], "e"),
+        // No hiragana counterparts.
+        'ヷ' => (&["β"], "a"),
+        'ヸ' => (&["β"], "i"),
+        'ヹ' => (&["β"], "e"),
+        'ヺ' => (&["β"], "o"),
+        _ => return None,
+    };
+    Some(s)
+}
+
+// Vowel of a small kana that replaces the vowel of the syllable before it,
+// and the glide it adds after the consonant (ゃゅょ and ゎ).
+fn small(c: char) -> Option<(&'static str, Option<&'static str>)> {
+    match c {
+        'ゃ' => Some(("a", Some("j"))),
+        'ゅ' => Some(("ɯ", Some("j"))),
+        'ょ' => Some(("o", Some("j"))),
+        'ゎ' => Some(("a", Some("w"))),
+        'ぁ' => Some(("a", None)),
+        'ぃ' => Some(("i", None)),
+        'ぅ' => Some(("ɯ", None)),
+        'ぇ' => Some(("e", None)),
+        'ぉ' => Some(("o", None)),
+        _ => None,
+    }
+}
+
+// The particles は and へ, read /wa/ and /e/.
+fn particle(kana: &str) -> Option<(&'static [&'static str], &'static str)> {
+    match kana {
+        "は" => Some((&["w"], "a")),
+        "へ" => Some((&[], "e")),
+        _ => None,
+    }
+}
+
+fn to_hiragana(c: char) -> char {
+    match c {
+        'ァ'..='ヶ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        _ => c,
+    }
+}
+
+// The moraic nasal takes the place of the consonant that follows it.
+fn moraic_nasal(next: Option<&Mora>) -> &'static str {
+    match next.and_then(|m| m.consonant.first()) {
+        Some(&("p" | "b" | "m")) => "m",
+        Some(&("t" | "d" | "n" | "s" | "z" | "ɾ")) => "n",
+        Some(&("ɕ" | "ʑ" | "ɲ" | "ç" | "j")) => "ɲ",
+        Some(&("k" | "g")) => "ŋ",
+        _ => "ɴ",
+    }
+}
+
+/// Splits hiragana or katakana into moras. Spaces and Japanese punctuation
+/// become pauses.
+///
+/// A hiragana は or へ that ends a word, before a pause or the end of the
+/// text, is read as the particle /wa/ or /e/; を is always /o/. Kana carry
+/// no other word boundaries, so separate particles from the next word with
+/// a space, and write words that end in /ha/ or /he/, like はは, in katakana.
+pub fn parse(text: &str) -> Result<Vec<Mora>, KanaError> {
+    let mut moras: Vec<Mora> = Vec::new();
+    // Indices of ん and っ, resolved once the following mora is known.
+    let mut pending: Vec<usize> = Vec::new();
+
+    for raw in text.chars() {
+        let c = to_hiragana(raw);
+        if let Some((vowel, glide)) = small(c) {
+            let prev = moras
+                .last_mut()
+                .filter(|m| m.vowel.is_some() && m.kana != "ー")
+                .ok_or(KanaError::Misplaced(raw))?;
+            if prev.consonant.is_empty() {
+                // うぃ and いぇ glide into the new vowel.
+                prev.consonant
+                    .push(if prev.vowel == Some("i") { "j" } else { "w" });
+            } else if let Some(glide) = glide {
+                if glide == "w" || !matches!(prev.consonant.last(), Some(&("ɕ" | "ʑ" | "ç" | "ɲ")))
+                {
+                    prev.consonant.push(glide);
+                }
+            }
+            prev.vowel = Some(vowel);
+            prev.kana.push(raw);
+            continue;
+        }
+
+        let mora = match c {
+            'ん' | 'っ' => {
+                pending.push(moras.len());
+                Mora {
+                    kana: raw.to_string(),
+                    consonant: Vec::new(),
+                    vowel: None,
+                }
+            }
+            'ー' => {
+                let vowel = moras
+                    .last()
+                    .and_then(|m| m.vowel)
+                    .ok_or(KanaError::Misplaced(raw))?;
+                Mora {
+                    kana: raw.to_string(),
+                    consonant: Vec::new(),
+                    vowel: Some(vowel),
+                }
+            }
+            ' ' | '　' | '、' | '。' | '・' | '！' | '？' => Mora {
+                kana: raw.to_string(),
+                consonant: vec!["_"],
+                vowel: None,
+            },
+            _ => {
+                let (consonant, vowel) = syllable(c).ok_or(KanaError::UnknownCharacter(raw))?;
+                Mora {
+                    kana: raw.to_string(),
+                    consonant: consonant.to_vec(),
+                    vowel: Some(vowel),
+                }
+            }
+        };
+        moras.push(mora);
+    }
+
+    for i in 0..moras.len() {
+        let word_end = moras.get(i + 1).is_none_or(Mora::is_pause);
+        if let Some((consonant, vowel)) = particle(&moras[i].kana).filter(|_| word_end) {
+            moras[i].consonant = consonant.to_vec();
+            moras[i].vowel = Some(vowel);
+        }
+    }
+
+    for &i in pending.iter().rev() {
+        let next = moras.get(i + 1);
+        let consonant = if moras[i].kana.chars().map(to_hiragana).eq(['ん']) {
+            moraic_nasal(next)
+        } else {
+            // A geminate holds the next consonant; before a vowel or a pause
+            // it closes the glottis instead.
+            match next.and_then(|m| m.consonant.first()) {
+                Some(&c) if c != "_" => c,
+                _ => "_",
+            }
+        };
+        moras[i].consonant = vec![consonant];
+    }
+
+    Ok(moras)
+}
+
+/// Turns kana into a `Sequence` with a Tokyo-style pitch accent.
+#[derive(Clone, Debug)]
+pub struct KanaReader {
+    pub inventory: PhonemeInventory,
+    /// Length of one mora in seconds.
+    pub mora_duration: f64,
+    /// Pitch of low moras in Hz.
+    pub pitch: f64,
+    /// Rise of high moras above `pitch`, in semitones.
+    pub accent_height: f64,
+}
+
+impl KanaReader {
+    pub fn new() -> Self {
+        KanaReader {
+            inventory: PhonemeInventory::ipa(),
+            mora_duration: 0.13,
+            pitch: 120.0,
+            accent_height: 3.0,
+        }
+    }
+
+    /// Whether mora `index` is high for an accent on mora `accent`, counted
+    /// from 1. Accent 0 is the flat pattern that stays high to the end.
+    pub fn is_high(index: usize, accent: usize) -> bool {
+        match accent {
+            1 => index == 0,
+            _ if index == 0 => false,
+            0 => true,
+            _ => index < accent,
+        }
+    }
+
+    // Time the consonant takes out of its mora.
+    fn consonant_duration(&self, manner: Manner) -> f64 {
+        let share = match manner {
+            Manner::Stop => 0.35,
+            Manner::Fricative => 0.5,
+            Manner::Nasal => 0.4,
+            Manner::Tap => 0.15,
+            Manner::Approximant => 0.3,
+            Manner::Vowel | Manner::Silence => 0.4,
+        };
+        share * self.mora_duration
+    }
+
+    /// Reads `text` with the accent nucleus on mora `accent` (0 for none)
+    /// and ends with a short pause.
+    pub fn sequence(&self, text: &str, accent: usize) -> Result<Sequence, KanaError> {
+        let moras = parse(text)?;
+        let mut seq = Sequence::new();
+        let high = self.pitch * 2f64.powf(self.accent_height / 12.0);
+
+        // The contour restarts after every pause.
+        let mut phrase_start = 0;
+        for (i, mora) in moras.iter().enumerate() {
+            if mora.is_pause() {
+                phrase_start = i + 1;
+            }
+            let pitch = if i >= phrase_start && Self::is_high(i - phrase_start, accent) {
+                high
+            } else {
+                self.pitch
+            };
+
+            let mut remaining = self.mora_duration;
+            for &symbol in &mora.consonant {
+                let phoneme = self
+                    .inventory
+                    .get(symbol)
+                    .ok_or(KanaError::MissingPhoneme(symbol))?;
+                let duration = if mora.vowel.is_some() {
+                    let d = self
+                        .consonant_duration(phoneme.manner)
+                        .min(remaining / (mora.consonant.len() + 1) as f64);
+                    remaining -= d;
+                    d
+                } else {
+                    remaining / mora.consonant.len() as f64
+                };
+                seq.push(phoneme.target.clone(), duration, pitch);
+            }
+            if let Some(symbol) = mora.vowel {
+                let phoneme = self
+                    .inventory
+                    .get(symbol)
+                    .ok_or(KanaError::MissingPhoneme(symbol))?;
+                seq.push(phoneme.target.clone(), remaining, pitch);
+            }
+        }
+
+        let pause = self
+            .inventory
+            .get("_")
+            .ok_or(KanaError::MissingPhoneme("_"))?;
+        seq.push(pause.target.clone(), self.mora_duration, self.pitch);
+        Ok(seq)
+    }
+}
+
+impl Default for KanaReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spell(text: &str) -> Vec<Vec<&'static str>> {
+        parse(text)
+            .unwrap()
+            .iter()
+            .map(|m| m.phonemes().collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_konnichiwa() {
+        assert_eq!(
+            spell("こんにちは"),
+            vec![
+                vec!["k", "o"],
+                vec!["ɲ"],
+                vec!["ɲ", "i"],
+                vec!["t", "ɕ", "i"],
+                vec!["w", "a"],
+            ]
+        );
+        // Katakana is never a particle.
+        assert_eq!(spell("コンニチハ")[4], vec!["h", "a"]);
+    }
+
+    #[test]
+    fn test_particles() {
+        assert_eq!(spell("わたしは がっこうへ"), spell("わたしわ がっこうえ"));
+        assert_eq!(spell("はな")[0], vec!["h", "a"]);
+        assert_eq!(spell("へやを")[0], vec!["h", "e"]);
+        assert_eq!(spell("へやを")[2], vec!["o"]);
+    }
+
+    #[test]
+    fn test_rare_kana() {
+        assert_eq!(spell("くゎ"), vec![vec!["k", "w", "a"]]);
+        assert_eq!(spell("ヵヶ"), vec![vec!["k", "a"], vec!["k", "e"]]);
+        assert_eq!(
+            spell("ヷヸヹヺ"),
+            vec![
+                vec!["β", "a"],
+                vec!["β", "i"],
+                vec!["β", "e"],
+                vec!["β", "o"]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_special_moras() {
+        assert_eq!(
+            spell("きって"),
+            vec![vec!["k", "i"], vec!["t"], vec!["t", "e"]]
+        );
+        assert_eq!(
+            spell("ラーメン"),
+            vec![vec!["ɾ", "a"], vec!["a"], vec!["m", "e"], vec!["ɴ"]]
+        );
+        assert_eq!(spell("きょう"), vec![vec!["k", "j", "o"], vec!["ɯ"]]);
+        assert_eq!(spell("しゃ"), vec![vec!["ɕ", "a"]]);
+        assert_eq!(spell("さんぽ")[1], vec!["m"]);
+        assert_eq!(parse("ーあ"), Err(KanaError::Misplaced('ー')));
+        assert_eq!(parse("abc"), Err(KanaError::UnknownCharacter('a')));
+    }
+
+    #[test]
+    fn test_pitch_accent() {
+        let pattern =
+            |accent| -> Vec<bool> { (0..4).map(|i| KanaReader::is_high(i, accent)).collect() };
+        assert_eq!(pattern(0), vec![false, true, true, true]);
+        assert_eq!(pattern(1), vec![true, false, false, false]);
+        assert_eq!(pattern(3), vec![false, true, true, false]);
+    }
+
+    #[test]
+    fn test_sequence_timing() {
+        let reader = KanaReader::new();
+        let seq = reader.sequence("こんにちは", 0).unwrap();
+        // Five moras and the closing pause.
+        assert!((seq.duration() - 6.0 * reader.mora_duration).abs() < 1e-9);
+        let first = seq.gestures()[0].pitch;
+        let last_vowel = seq.gestures()[seq.len() - 2].pitch;
+        assert!(last_vowel > first);
+    }
+}
@@ -1,5 +1,20 @@
+pub mod automation;
+pub mod choir;
+pub mod config;
 pub mod consts;
+pub mod float;
 pub mod glottis;
+pub mod kana;
+pub mod midi;
+pub mod noise;
+#[cfg(feature = "osc")]
+pub mod osc;
+pub mod phoneme;
+#[cfg(feature = "preset")]
+pub mod preset;
+pub mod score;
+pub mod sequence;
+pub mod source;
 pub mod tract;
 pub mod transient;
 pub mod voc;
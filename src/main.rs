@@ -1,12 +1,23 @@
-use hound;
 use std::error::Error; // WAVファイルの読み書きに使用
 
+use pinktrombone::config::VocConfig;
+use pinktrombone::midi::{MidiMapping, Smf};
 use pinktrombone::voc::{Mode, Voc, VocDemoD};
 
 const SAMPLE_RATE: f64 = 44100.0;
 const BUFFER_FRAMES: usize = 1024;
 
 fn main() -> Result<(), Box<dyn Error>> {
+    // `pinktrombone song.mid [out.wav]` renders a MIDI file instead of the demos.
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(input) = args.get(1) {
+        let output = match args.get(2) {
+            Some(output) => output.clone(),
+            None => format!("{}.wav", input.trim_end_matches(".mid")),
+        };
+        return render_midi(input, &output);
+    }
+
     throat_and_lips()?;
     tongue_index()?;
     tongue_diameter()?;
@@ -36,30 +47,21 @@ fn callme(output: &mut [(f32, f32)], vd: &mut VocDemoD) {
     }
 }
 
-fn setup() -> VocDemoD {
-    let mut vdd = VocDemoD::new(SAMPLE_RATE, BUFFER_FRAMES);
+fn setup() -> Result<VocDemoD, Box<dyn Error>> {
+    let mut vdd = VocDemoD::new(SAMPLE_RATE, BUFFER_FRAMES)?;
     vdd.voc.set_frequency(160.0);
-    vdd
+    Ok(vdd)
 }
 
 fn play_update<F>(mut update_fn: F, filename: &str) -> Result<(), Box<dyn Error>>
 where
     F: FnMut(&mut Voc, f64),
 {
-    let mut voc = Voc::new(
-        SAMPLE_RATE,
-        BUFFER_FRAMES,
-        0.125,
-        400.0,
-        0.6,
-        44,
-        28,
-        17,
-        32,
-        12,
-        6,
-        39,
-    );
+    let config = VocConfig::builder()
+        .samplerate(SAMPLE_RATE)
+        .chunk(BUFFER_FRAMES)
+        .build()?;
+    let mut voc = Voc::from_config(config)?;
     let mut x = 0.0;
     update_fn(&mut voc, x);
 
@@ -89,6 +91,33 @@ where
     Ok(())
 }
 
+fn render_midi(input: &str, output: &str) -> Result<(), Box<dyn Error>> {
+    let smf = Smf::open(input)?;
+    let config = VocConfig::builder()
+        .samplerate(SAMPLE_RATE)
+        .chunk(BUFFER_FRAMES)
+        .build()?;
+    let mut voc = Voc::from_config(config)?;
+    let samples = MidiMapping::default().render(&smf, &mut voc);
+
+    let mut writer = hound::WavWriter::create(
+        output,
+        hound::WavSpec {
+            channels: 2,
+            sample_rate: SAMPLE_RATE as u32,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        },
+    )?;
+    for &sample in &samples {
+        writer.write_sample(sample as f32)?;
+        writer.write_sample(sample as f32)?;
+    }
+    writer.finalize()?;
+
+    Ok(())
+}
+
 fn tongue_index() -> Result<(), Box<dyn Error>> {
     play_update(
         |voc, x| {
@@ -120,7 +149,7 @@ fn nothing() -> Result<(), Box<dyn Error>> {
 }
 
 fn throat_and_lips() -> Result<(), Box<dyn Error>> {
-    let mut vdd: VocDemoD = setup();
+    let mut vdd: VocDemoD = setup()?;
     let mut x: f64 = 0.0;
     let mut y: f64 = 0.0;
 
@@ -129,9 +158,10 @@ fn throat_and_lips() -> Result<(), Box<dyn Error>> {
     vdd.voc.set_tract_diameters(0..n_t, vec![throat; n_t]);
 
     let mut lips = y.sin() * 1.5 / 2.0 + 0.75;
-    let n_l = vdd.voc.tract_size() - 39;
+    let lip_start = vdd.voc.config().lip_start;
+    let n_l = vdd.voc.tract_size() - lip_start;
     vdd.voc
-        .set_tract_diameters(39..vdd.voc.tract_size(), vec![lips; n_l]);
+        .set_tract_diameters(lip_start..vdd.voc.tract_size(), vec![lips; n_l]);
 
     let mut writer = hound::WavWriter::create(
         "data/throat_and_lips.wav",
@@ -154,7 +184,7 @@ fn throat_and_lips() -> Result<(), Box<dyn Error>> {
         x += 0.55;
         lips = x.sin() * 1.5 / 2.0 + 0.75;
         vdd.voc
-            .set_tract_diameters(39..vdd.voc.tract_size(), vec![lips; n_l]);
+            .set_tract_diameters(lip_start..vdd.voc.tract_size(), vec![lips; n_l]);
 
         y += 0.5;
         throat = y.sin() * 1.5 / 2.0 + 0.75;
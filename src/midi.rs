@@ -0,0 +1,540 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::automation::{Automation, Param};
+use crate::float::Float;
+use crate::score::midi_to_frequency;
+use crate::sequence::to_samples;
+use crate::voc::Voc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOn {
+        channel: u8,
+        key: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        key: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    /// Bend from -8192 to 8191, centred on 0.
+    PitchBend {
+        channel: u8,
+        value: i16,
+    },
+}
+
+impl MidiMessage {
+    pub fn channel(&self) -> u8 {
+        match *self {
+            MidiMessage::NoteOn { channel, .. }
+            | MidiMessage::NoteOff { channel, .. }
+            | MidiMessage::ControlChange { channel, .. }
+            | MidiMessage::PitchBend { channel, .. } => channel,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MidiEvent {
+    /// Seconds from the start of the file.
+    pub time: f64,
+    pub message: MidiMessage,
+}
+
+#[derive(Debug)]
+pub enum MidiError {
+    Io(io::Error),
+    NotSmf,
+    /// Only formats 0 and 1 are read.
+    UnsupportedFormat(u16),
+    Truncated,
+    /// A data byte with no running status to apply it to.
+    MissingStatus,
+}
+
+impl fmt::Display for MidiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiError::Io(e) => write!(f, "could not read MIDI file: {}", e),
+            MidiError::NotSmf => write!(f, "not a standard MIDI file"),
+            MidiError::UnsupportedFormat(format) => {
+                write!(f, "SMF format {} is not supported", format)
+            }
+            MidiError::Truncated => write!(f, "MIDI data ends in the middle of a chunk"),
+            MidiError::MissingStatus => write!(f, "MIDI data byte without a status byte"),
+        }
+    }
+}
+
+impl Error for MidiError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MidiError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MidiError {
+    fn from(e: io::Error) -> Self {
+        MidiError::Io(e)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], MidiError> {
+        let end = self.pos.checked_add(len).ok_or(MidiError::Truncated)?;
+        let bytes = self.data.get(self.pos..end).ok_or(MidiError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, MidiError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, MidiError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, MidiError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    // Variable-length quantity, at most four bytes.
+    fn vlq(&mut self) -> Result<u32, MidiError> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let b = self.u8()?;
+            value = (value << 7) | (b & 0x7f) as u32;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(MidiError::Truncated)
+    }
+}
+
+enum TrackEvent {
+    Message(MidiMessage),
+    Tempo(u32),
+}
+
+fn read_track(data: &[u8]) -> Result<Vec<(u64, TrackEvent)>, MidiError> {
+    let mut r = Reader::new(data);
+    let mut events = Vec::new();
+    let mut tick = 0u64;
+    let mut running: Option<u8> = None;
+
+    while !r.is_empty() {
+        tick += r.vlq()? as u64;
+        let first = r.u8()?;
+        let (status, data1) = if first & 0x80 != 0 {
+            (first, None)
+        } else {
+            (running.ok_or(MidiError::MissingStatus)?, Some(first))
+        };
+
+        match status {
+            0xff => {
+                running = None;
+                let kind = r.u8()?;
+                let len = r.vlq()? as usize;
+                let body = r.bytes(len)?;
+                match kind {
+                    0x51 if len == 3 => {
+                        let tempo = u32::from_be_bytes([0, body[0], body[1], body[2]]);
+                        events.push((tick, TrackEvent::Tempo(tempo)));
+                    }
+                    0x2f => break,
+                    _ => {}
+                }
+            }
+            0xf0 | 0xf7 => {
+                running = None;
+                let len = r.vlq()? as usize;
+                r.bytes(len)?;
+            }
+            _ => {
+                running = Some(status);
+                let channel = status & 0x0f;
+                let data1 = match data1 {
+                    Some(b) => b,
+                    None => r.u8()?,
+                };
+                let message = match status & 0xf0 {
+                    0x80 => Some(MidiMessage::NoteOff {
+                        channel,
+                        key: data1,
+                    }),
+                    0x90 => {
+                        let velocity = r.u8()?;
+                        if velocity == 0 {
+                            Some(MidiMessage::NoteOff {
+                                channel,
+                                key: data1,
+                            })
+                        } else {
+                            Some(MidiMessage::NoteOn {
+                                channel,
+                                key: data1,
+                                velocity,
+                            })
+                        }
+                    }
+                    0xb0 => Some(MidiMessage::ControlChange {
+                        channel,
+                        controller: data1,
+                        value: r.u8()?,
+                    }),
+                    0xe0 => {
+                        let msb = r.u8()?;
+                        let value = (((msb as i16) << 7) | data1 as i16) - 8192;
+                        Some(MidiMessage::PitchBend { channel, value })
+                    }
+                    0xc0 | 0xd0 => None,
+                    _ => {
+                        // Polyphonic aftertouch
+                        r.u8()?;
+                        None
+                    }
+                };
+                if status & 0xf0 == 0x80 {
+                    // Release velocity
+                    r.u8()?;
+                }
+                if let Some(message) = message {
+                    events.push((tick, TrackEvent::Message(message)));
+                }
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+/// A standard MIDI file of format 0 or 1, with every track merged into one
+/// list of events timed in seconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Smf {
+    pub format: u16,
+    events: Vec<MidiEvent>,
+}
+
+impl Smf {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, MidiError> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, MidiError> {
+        let mut r = Reader::new(data);
+        if r.bytes(4).map_err(|_| MidiError::NotSmf)? != b"MThd" {
+            return Err(MidiError::NotSmf);
+        }
+        let len = r.u32()? as usize;
+        let mut header = Reader::new(r.bytes(len)?);
+        let format = header.u16()?;
+        let _tracks = header.u16()?;
+        let division = header.u16()?;
+        if format > 1 {
+            return Err(MidiError::UnsupportedFormat(format));
+        }
+
+        let mut merged = Vec::new();
+        while !r.is_empty() {
+            let kind = r.bytes(4)?;
+            let len = r.u32()? as usize;
+            let body = r.bytes(len)?;
+            if kind == b"MTrk" {
+                merged.extend(read_track(body)?);
+            }
+        }
+        // Stable, so simultaneous events keep their track order.
+        merged.sort_by_key(|&(tick, _)| tick);
+
+        // Seconds per tick, either from the tempo map or a fixed SMPTE rate.
+        let smpte = division & 0x8000 != 0;
+        let fixed = if smpte {
+            let fps = -((division >> 8) as u8 as i8) as f64;
+            let per_frame = (division & 0xff) as f64;
+            1.0 / (fps * per_frame)
+        } else {
+            0.0
+        };
+        let ticks_per_quarter = division.max(1) as f64;
+        let mut per_tick = 500_000.0 / 1e6 / ticks_per_quarter;
+
+        let mut events = Vec::new();
+        let (mut last_tick, mut time) = (0u64, 0.0);
+        for (tick, event) in merged {
+            time += (tick - last_tick) as f64 * if smpte { fixed } else { per_tick };
+            last_tick = tick;
+            match event {
+                TrackEvent::Tempo(tempo) => per_tick = tempo as f64 / 1e6 / ticks_per_quarter,
+                TrackEvent::Message(message) => events.push(MidiEvent { time, message }),
+            }
+        }
+
+        Ok(Smf { format, events })
+    }
+
+    pub fn events(&self) -> &[MidiEvent] {
+        &self.events
+    }
+
+    /// Time of the last event in seconds.
+    pub fn duration(&self) -> f64 {
+        self.events.last().map_or(0.0, |e| e.time)
+    }
+}
+
+/// Range a controller sweeps, from value 0 to value 127.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ControlRange {
+    pub controller: u8,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl ControlRange {
+    pub fn new(controller: u8, min: f64, max: f64) -> Self {
+        ControlRange {
+            controller,
+            min,
+            max,
+        }
+    }
+
+    fn map(&self, value: u8) -> f64 {
+        self.min + (self.max - self.min) * value as f64 / 127.0
+    }
+}
+
+/// How MIDI drives a single monophonic voice. The most recent held note
+/// sounds; releasing it falls back to the note held before it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MidiMapping {
+    /// Channel to follow, or all channels when `None`.
+    pub channel: Option<u8>,
+    /// Pitch bend range in semitones.
+    pub bend_range: f64,
+    pub tongue_index: Option<ControlRange>,
+    pub tongue_diameter: Option<ControlRange>,
+    pub lips: Option<ControlRange>,
+    pub velum: Option<ControlRange>,
+    pub tenseness: Option<ControlRange>,
+    /// Time rendered after the last event.
+    pub release: f64,
+}
+
+impl Default for MidiMapping {
+    fn default() -> Self {
+        MidiMapping {
+            channel: None,
+            bend_range: 2.0,
+            tongue_index: Some(ControlRange::new(16, 12.0, 29.0)),
+            tongue_diameter: Some(ControlRange::new(17, 2.05, 3.5)),
+            lips: Some(ControlRange::new(18, 0.0, 1.5)),
+            velum: Some(ControlRange::new(19, 0.01, 0.4)),
+            tenseness: Some(ControlRange::new(20, 0.0, 1.0)),
+            release: 0.3,
+        }
+    }
+}
+
+impl MidiMapping {
+    /// Turns the file into automation for `voc`, starting at its current
+    /// sample time. The voice is silent until the first note.
+    pub fn compile<F: Float>(&self, smf: &Smf, voc: &Voc<F>) -> Automation {
+        let origin = voc.sample_time();
+        let at = |seconds: f64| origin + to_samples(seconds, voc.sr);
+        let controls = [
+            (self.tongue_index, Param::TongueIndex),
+            (self.tongue_diameter, Param::TongueDiameter),
+            (self.lips, Param::Lips),
+            (self.velum, Param::Velum),
+            (self.tenseness, Param::Tenseness),
+        ];
+
+        let mut automation = Automation::new();
+        automation.set(Param::Voicing, origin, 0.0);
+        let mut held: Vec<u8> = Vec::new();
+        let mut bend = 0.0;
+
+        for event in smf.events() {
+            if self.channel.is_some_and(|c| c != event.message.channel()) {
+                continue;
+            }
+            let time = at(event.time);
+            match event.message {
+                MidiMessage::NoteOn { key, velocity, .. } => {
+                    held.retain(|&k| k != key);
+                    held.push(key);
+                    let f = midi_to_frequency(key as f64 + bend);
+                    automation.set(Param::Frequency, time, f);
+                    automation.set(Param::Intensity, time, velocity as f64 / 127.0);
+                    automation.set(Param::Voicing, time, 1.0);
+                }
+                MidiMessage::NoteOff { key, .. } => {
+                    let sounding = held.last() == Some(&key);
+                    held.retain(|&k| k != key);
+                    if !sounding {
+                        continue;
+                    }
+                    match held.last() {
+                        Some(&k) => {
+                            let f = midi_to_frequency(k as f64 + bend);
+                            automation.set(Param::Frequency, time, f);
+                        }
+                        None => automation.set(Param::Voicing, time, 0.0),
+                    }
+                }
+                MidiMessage::PitchBend { value, .. } => {
+                    bend = value as f64 / 8192.0 * self.bend_range;
+                    if let Some(&k) = held.last() {
+                        let f = midi_to_frequency(k as f64 + bend);
+                        automation.set(Param::Frequency, time, f);
+                    }
+                }
+                MidiMessage::ControlChange {
+                    controller, value, ..
+                } => {
+                    for (range, param) in controls {
+                        if let Some(range) = range.filter(|r| r.controller == controller) {
+                            automation.set(param, time, range.map(value));
+                        }
+                    }
+                }
+            }
+        }
+
+        automation
+    }
+
+    /// Renders the file through `voc` from its current position, replacing
+    /// its automation.
+    pub fn render(&self, smf: &Smf, voc: &mut Voc) -> Vec<f64> {
+        voc.set_automation(self.compile(smf, voc));
+        let mut out = vec![0.0; to_samples(smf.duration() + self.release, voc.sr) as usize];
+        voc.process(&mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::VocConfig;
+
+    fn chunk(kind: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut c = kind.to_vec();
+        c.extend((body.len() as u32).to_be_bytes());
+        c.extend(body);
+        c
+    }
+
+    fn file(format: u16, tracks: &[&[u8]]) -> Vec<u8> {
+        let mut header = format.to_be_bytes().to_vec();
+        header.extend((tracks.len() as u16).to_be_bytes());
+        header.extend(96u16.to_be_bytes());
+        let mut data = chunk(b"MThd", &header);
+        for t in tracks {
+            data.extend(chunk(b"MTrk", t));
+        }
+        data
+    }
+
+    // At 96 ticks per quarter and 120 bpm, 96 ticks last half a second.
+    const MELODY: &[u8] = &[
+        0x00, 0xff, 0x51, 0x03, 0x07, 0xa1, 0x20, // tempo 500000
+        0x00, 0x90, 60, 100, // note on
+        0x30, 0xb0, 16, 127, // tongue index CC
+        0x00, 0xe0, 0x00, 0x60, // bend up a semitone
+        0x30, 0x80, 60, 0, // note off
+        0x00, 0x90, 62, 64, // note on
+        0x60, 62, 0, // running status note off
+        0x00, 0xff, 0x2f, 0x00,
+    ];
+
+    #[test]
+    fn test_parse_times_events() {
+        let smf = Smf::parse(&file(0, &[MELODY])).unwrap();
+        let times: Vec<f64> = smf.events().iter().map(|e| e.time).collect();
+        assert_eq!(times, vec![0.0, 0.25, 0.25, 0.5, 0.5, 1.0]);
+        assert_eq!(
+            smf.events()[2].message,
+            MidiMessage::PitchBend {
+                channel: 0,
+                value: 4096
+            }
+        );
+        assert_eq!(
+            smf.events()[5].message,
+            MidiMessage::NoteOff {
+                channel: 0,
+                key: 62
+            }
+        );
+    }
+
+    #[test]
+    fn test_format_1_merges_tracks() {
+        let tempo: &[u8] = &[
+            0x00, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40, 0x00, 0xff, 0x2f, 0x00,
+        ];
+        let notes: &[u8] = &[0x60, 0x91, 64, 90, 0x00, 0xff, 0x2f, 0x00];
+        let smf = Smf::parse(&file(1, &[tempo, notes])).unwrap();
+        assert_eq!(smf.events().len(), 1);
+        assert!((smf.events()[0].time - 1.0).abs() < 1e-12);
+        assert!(matches!(
+            Smf::parse(&file(2, &[notes])),
+            Err(MidiError::UnsupportedFormat(2))
+        ));
+        assert!(matches!(
+            Smf::parse(&file(0, &[&notes[..3]])),
+            Err(MidiError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn test_mapping_drives_the_voice() {
+        let smf = Smf::parse(&file(0, &[MELODY])).unwrap();
+        let v = Voc::from_config(VocConfig::default()).unwrap();
+        let a = MidiMapping::default().compile(&smf, &v);
+        let at = |seconds: f64| to_samples(seconds, v.sr);
+
+        let f = a.value_at(Param::Frequency, at(0.1)).unwrap();
+        assert!((f - midi_to_frequency(60.0)).abs() < 1e-9);
+        let f = a.value_at(Param::Frequency, at(0.3)).unwrap();
+        assert!((f - midi_to_frequency(61.0)).abs() < 1e-9);
+        assert_eq!(a.value_at(Param::TongueIndex, at(0.3)), Some(29.0));
+        assert_eq!(a.value_at(Param::Intensity, at(0.1)), Some(100.0 / 127.0));
+        assert_eq!(a.value_at(Param::Voicing, at(0.7)), Some(1.0));
+        assert_eq!(a.value_at(Param::Voicing, at(1.0)), Some(0.0));
+    }
+}
@@ -0,0 +1,124 @@
+use rand::Rng;
+use std::f64::consts::PI;
+
+/// Second-order bandpass filter (constant 0 dB peak gain), used to shape white
+/// noise into aspiration and frication sources.
+#[derive(Clone)]
+pub struct BandpassFilter {
+    b0: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+impl BandpassFilter {
+    pub fn new(sr: f64, center: f64, q: f64) -> Self {
+        let mut filter = BandpassFilter {
+            b0: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        };
+        filter.set(sr, center, q);
+        filter
+    }
+
+    pub fn set(&mut self, sr: f64, center: f64, q: f64) {
+        let w0 = 2.0 * PI * center / sr;
+        let alpha = w0.sin() / (2.0 * q);
+        let a0 = 1.0 + alpha;
+
+        self.b0 = alpha / a0;
+        self.b2 = -alpha / a0;
+        self.a1 = -2.0 * w0.cos() / a0;
+        self.a2 = (1.0 - alpha) / a0;
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.b2 * self.x2 - self.a1 * self.y1 - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// Band-limited random signal: uniform random values placed `1 / rate`
+/// seconds apart with cosine interpolation in between.
+#[derive(Clone)]
+pub struct SmoothNoise {
+    rate: f64,
+    index: u64,
+    prev: f64,
+    next: f64,
+}
+
+impl SmoothNoise {
+    pub fn new(rate: f64) -> Self {
+        SmoothNoise {
+            rate,
+            index: 0,
+            prev: 0.0,
+            next: 0.0,
+        }
+    }
+
+    /// Samples the noise at `time` seconds. `time` must not decrease between calls.
+    pub fn at<R: Rng + ?Sized>(&mut self, rng: &mut R, time: f64) -> f64 {
+        let x = time * self.rate;
+        while x >= (self.index + 1) as f64 {
+            self.index += 1;
+            self.prev = self.next;
+            self.next = rng.gen_range(-1.0..1.0);
+        }
+
+        let frac = x - self.index as f64;
+        let w = (1.0 - (PI * frac).cos()) * 0.5;
+        self.prev * (1.0 - w) + self.next * w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gain(filter: &mut BandpassFilter, freq: f64) -> f64 {
+        let mut peak: f64 = 0.0;
+        for i in 0..44100 {
+            let y = filter.process((2.0 * PI * freq * i as f64 / 44100.0).sin());
+            if i > 22050 {
+                peak = peak.max(y.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn test_bandpass_passes_center() {
+        assert!((gain(&mut BandpassFilter::new(44100.0, 500.0, 0.5), 500.0) - 1.0).abs() < 0.01);
+        assert!(gain(&mut BandpassFilter::new(44100.0, 500.0, 5.0), 50.0) < 0.05);
+        assert!(gain(&mut BandpassFilter::new(44100.0, 500.0, 5.0), 5000.0) < 0.05);
+    }
+
+    #[test]
+    fn test_smooth_noise_is_bounded_and_continuous() {
+        let mut noise = SmoothNoise::new(5.0);
+        let rng = &mut rand::thread_rng();
+        let mut last = noise.at(rng, 0.0);
+        for i in 1..44100 {
+            let x = noise.at(rng, i as f64 / 44100.0);
+            assert!(x.abs() <= 1.0);
+            assert!((x - last).abs() < 0.01);
+            last = x;
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::voc::Voc;
+
+// Commands waiting for the audio thread. Later ones are dropped while the
+// queue is full, so a flood of messages can't grow it without bound.
+const QUEUE_LEN: usize = 1024;
+
+// Largest UDP payload.
+const MAX_PACKET: usize = 65535;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    Double(f64),
+    String(String),
+}
+
+impl OscArg {
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            OscArg::Int(i) => Some(i as f64),
+            OscArg::Float(f) => Some(f as f64),
+            OscArg::Double(d) => Some(d),
+            OscArg::String(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+// Reads a null-terminated string padded to four bytes.
+fn read_string(data: &[u8], pos: &mut usize) -> Option<String> {
+    let rest = data.get(*pos..)?;
+    let len = rest.iter().position(|&b| b == 0)?;
+    let s = std::str::from_utf8(&rest[..len]).ok()?.to_string();
+    *pos += (len + 4) & !3;
+    Some(s)
+}
+
+fn read_bytes<const N: usize>(data: &[u8], pos: &mut usize) -> Option<[u8; N]> {
+    let bytes = data.get(*pos..*pos + N)?.try_into().ok()?;
+    *pos += N;
+    Some(bytes)
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(s.as_bytes());
+    out.resize(out.len() + 4 - s.len() % 4, 0);
+}
+
+impl OscMessage {
+    pub fn new(address: &str, args: Vec<OscArg>) -> Self {
+        OscMessage {
+            address: address.to_string(),
+            args,
+        }
+    }
+
+    /// Decodes a packet, flattening bundles into their messages. Malformed
+    /// packets and unsupported argument types yield nothing.
+    pub fn decode(packet: &[u8]) -> Vec<OscMessage> {
+        let mut messages = Vec::new();
+        Self::decode_into(packet, &mut messages);
+        messages
+    }
+
+    fn decode_into(packet: &[u8], messages: &mut Vec<OscMessage>) {
+        if packet.starts_with(b"#bundle\0") {
+            // Skip the tag and the time tag; elements follow.
+            let mut pos = 16;
+            while let Some(size) = read_bytes::<4>(packet, &mut pos) {
+                let size = u32::from_be_bytes(size) as usize;
+                let Some(element) = packet.get(pos..pos + size) else {
+                    return;
+                };
+                Self::decode_into(element, messages);
+                pos += size;
+            }
+        } else if let Some(message) = Self::decode_message(packet) {
+            messages.push(message);
+        }
+    }
+
+    fn decode_message(packet: &[u8]) -> Option<OscMessage> {
+        let mut pos = 0;
+        let address = read_string(packet, &mut pos)?;
+        if !address.starts_with('/') {
+            return None;
+        }
+        let tags = if pos < packet.len() {
+            read_string(packet, &mut pos)?
+        } else {
+            String::from(",")
+        };
+
+        let mut args = Vec::new();
+        for tag in tags.strip_prefix(',')?.chars() {
+            let arg = match tag {
+                'i' => OscArg::Int(i32::from_be_bytes(read_bytes(packet, &mut pos)?)),
+                'f' => OscArg::Float(f32::from_be_bytes(read_bytes(packet, &mut pos)?)),
+                'd' => OscArg::Double(f64::from_be_bytes(read_bytes(packet, &mut pos)?)),
+                's' => OscArg::String(read_string(packet, &mut pos)?),
+                _ => return None,
+            };
+            args.push(arg);
+        }
+        Some(OscMessage { address, args })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_string(&mut out, &self.address);
+        let mut tags = String::from(",");
+        for arg in &self.args {
+            tags.push(match arg {
+                OscArg::Int(_) => 'i',
+                OscArg::Float(_) => 'f',
+                OscArg::Double(_) => 'd',
+                OscArg::String(_) => 's',
+            });
+        }
+        write_string(&mut out, &tags);
+        for arg in &self.args {
+            match arg {
+                OscArg::Int(i) => out.extend(i.to_be_bytes()),
+                OscArg::Float(f) => out.extend(f.to_be_bytes()),
+                OscArg::Double(d) => out.extend(d.to_be_bytes()),
+                OscArg::String(s) => write_string(&mut out, s),
+            }
+        }
+        out
+    }
+}
+
+/// A parameter change for `Voc`, decoded from an OSC message.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VocCommand {
+    Frequency(f64),
+    Tenseness(f64),
+    Tongue { index: f64, diameter: f64 },
+    Lips(f64),
+    Velum(f64),
+    Diameter { index: usize, diameter: f64 },
+}
+
+impl VocCommand {
+    /// Maps `/voc/frequency`, `/voc/tenseness`, `/voc/tongue <index>
+    /// <diameter>`, `/voc/lips`, `/voc/velum` and `/voc/diameter/<i>`.
+    pub fn from_message(message: &OscMessage) -> Option<VocCommand> {
+        let arg = |i: usize| message.args.get(i).and_then(OscArg::as_f64);
+        let path = message.address.strip_prefix("/voc/")?;
+        let command = match path {
+            "frequency" => VocCommand::Frequency(arg(0)?),
+            "tenseness" => VocCommand::Tenseness(arg(0)?),
+            "tongue" => VocCommand::Tongue {
+                index: arg(0)?,
+                diameter: arg(1)?,
+            },
+            "lips" => VocCommand::Lips(arg(0)?),
+            "velum" => VocCommand::Velum(arg(0)?),
+            _ => {
+                let index = path.strip_prefix("diameter/")?.parse().ok()?;
+                VocCommand::Diameter {
+                    index,
+                    diameter: arg(0)?,
+                }
+            }
+        };
+        Some(command)
+    }
+
+    pub fn apply(&self, voc: &mut Voc) {
+        match *self {
+            VocCommand::Frequency(f) => voc.set_frequency(f),
+            VocCommand::Tenseness(t) => voc.set_tenseness(t),
+            VocCommand::Tongue { index, diameter } => voc.tongue_shape(index, diameter),
+            VocCommand::Lips(d) => voc.set_lips(d),
+            VocCommand::Velum(v) => voc.set_velum(v),
+            VocCommand::Diameter { index, diameter } => voc.set_tract_diameter(index, diameter),
+        }
+    }
+}
+
+/// Listens for OSC over UDP on a background thread. Stops when dropped.
+pub struct OscServer {
+    local_addr: SocketAddr,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// The audio thread's end of an `OscServer`.
+pub struct OscReceiver {
+    commands: Receiver<VocCommand>,
+}
+
+impl OscServer {
+    /// Binds `addr` and starts listening. Port 0 picks a free port, see
+    /// `local_addr`. Up to 1024 commands wait for the receiver; messages
+    /// arriving while it is full are dropped.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<(OscServer, OscReceiver)> {
+        let socket = UdpSocket::bind(addr)?;
+        // Wake up now and then to notice shutdown.
+        socket.set_read_timeout(Some(Duration::from_millis(50)))?;
+        let local_addr = socket.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+        let (tx, rx) = mpsc::sync_channel(QUEUE_LEN);
+
+        let flag = Arc::clone(&running);
+        let thread = thread::spawn(move || listen(socket, tx, flag));
+
+        let server = OscServer {
+            local_addr,
+            running,
+            thread: Some(thread),
+        };
+        Ok((server, OscReceiver { commands: rx }))
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+fn listen(socket: UdpSocket, commands: SyncSender<VocCommand>, running: Arc<AtomicBool>) {
+    let mut packet = vec![0u8; MAX_PACKET];
+    while running.load(Ordering::Relaxed) {
+        let len = match socket.recv_from(&mut packet) {
+            Ok((len, _)) => len,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            Err(_) => break,
+        };
+        for message in OscMessage::decode(&packet[..len]) {
+            if let Some(command) = VocCommand::from_message(&message) {
+                match commands.try_send(command) {
+                    Ok(()) | Err(TrySendError::Full(_)) => {}
+                    Err(TrySendError::Disconnected(_)) => return,
+                }
+            }
+        }
+    }
+}
+
+impl Drop for OscServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl OscReceiver {
+    /// Applies every command received so far, without blocking. Call it from
+    /// the audio thread between render calls. Returns how many were applied.
+    pub fn apply_pending(&self, voc: &mut Voc) -> usize {
+        let mut applied = 0;
+        while let Ok(command) = self.commands.try_recv() {
+            command.apply(voc);
+            applied += 1;
+        }
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::VocConfig;
+    use std::time::Instant;
+
+    #[test]
+    fn test_round_trip_and_mapping() {
+        let message = OscMessage::new(
+            "/voc/tongue",
+            vec![OscArg::Float(20.0), OscArg::Double(2.5)],
+        );
+        let decoded = OscMessage::decode(&message.encode());
+        assert_eq!(decoded, vec![message]);
+        assert_eq!(
+            VocCommand::from_message(&decoded[0]),
+            Some(VocCommand::Tongue {
+                index: 20.0,
+                diameter: 2.5
+            })
+        );
+
+        let diameter = OscMessage::new("/voc/diameter/40", vec![OscArg::Int(1)]);
+        assert_eq!(
+            VocCommand::from_message(&diameter),
+            Some(VocCommand::Diameter {
+                index: 40,
+                diameter: 1.0
+            })
+        );
+        assert_eq!(
+            VocCommand::from_message(&OscMessage::new("/voc/lips", vec![])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_udp_updates_reach_the_voice() {
+        let (server, receiver) = OscServer::bind("127.0.0.1:0").unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let mut bundle = b"#bundle\0".to_vec();
+        bundle.extend(1u64.to_be_bytes());
+        for message in [
+            OscMessage::new("/voc/frequency", vec![OscArg::Float(220.0)]),
+            OscMessage::new("/voc/velum", vec![OscArg::Float(0.3)]),
+        ] {
+            let bytes = message.encode();
+            bundle.extend((bytes.len() as u32).to_be_bytes());
+            bundle.extend(bytes);
+        }
+        client.send_to(&bundle, server.local_addr()).unwrap();
+
+        let mut voc = Voc::from_config(VocConfig::default()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut applied = 0;
+        while applied < 2 && Instant::now() < deadline {
+            applied += receiver.apply_pending(&mut voc);
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(voc.frequency(), 220.0);
+        assert!((voc.velum() - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_full_queue_drops_messages() {
+        let (server, receiver) = OscServer::bind("127.0.0.1:0").unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        // One packet far larger than an Ethernet frame.
+        let mut bundle = b"#bundle\0".to_vec();
+        bundle.extend(1u64.to_be_bytes());
+        for i in 0..QUEUE_LEN + 100 {
+            let message = OscMessage::new("/voc/frequency", vec![OscArg::Int(100 + i as i32)]);
+            let bytes = message.encode();
+            bundle.extend((bytes.len() as u32).to_be_bytes());
+            bundle.extend(bytes);
+        }
+        client.send_to(&bundle, server.local_addr()).unwrap();
+        thread::sleep(Duration::from_millis(500));
+
+        let mut voc = Voc::from_config(VocConfig::default()).unwrap();
+        assert_eq!(receiver.apply_pending(&mut voc), QUEUE_LEN);
+        assert_eq!(voc.frequency(), (100 + QUEUE_LEN - 1) as f64);
+    }
+}
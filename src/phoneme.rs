@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+/// A local narrowing of the tract, laid over the tongue shape the way a
+/// finger on the Pink Trombone tract does.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Constriction {
+    pub index: f64,
+    pub diameter: f64,
+}
+
+/// Articulator settings for one sound. Indices are given for the default
+/// 44-segment tract and scaled to the voice's own length when applied.
+/// `None` fields leave the articulator where the previous sound put it, and
+/// constrictions last only until the next target is applied.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArticulatoryTarget {
+    /// Tongue index and diameter, as taken by `Voc::tongue_shape`.
+    pub tongue: Option<(f64, f64)>,
+    pub lips: Option<f64>,
+    pub velum: Option<f64>,
+    pub tenseness: Option<f64>,
+    pub constrictions: Vec<Constriction>,
+    pub voiced: bool,
+    /// Whether air keeps flowing while the voice is off, giving the
+    /// aspiration of /h/. Only silence holds its breath.
+    pub breathing: bool,
+    /// Turbulence noise level, as taken by `Voc::set_fricative_intensity`.
+    /// Vowels and approximants keep it at zero so their narrow passages do
+    /// not hiss.
+    pub frication: f64,
+}
+
+impl Default for ArticulatoryTarget {
+    fn default() -> Self {
+        ArticulatoryTarget {
+            tongue: None,
+            lips: None,
+            velum: None,
+            tenseness: None,
+            constrictions: Vec::new(),
+            voiced: true,
+            breathing: true,
+            frication: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Manner {
+    Vowel,
+    Nasal,
+    Stop,
+    Fricative,
+    Approximant,
+    Tap,
+    Silence,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Phoneme {
+    pub symbol: String,
+    pub manner: Manner,
+    pub target: ArticulatoryTarget,
+}
+
+pub const VELUM_CLOSED: f64 = 0.01;
+pub const VELUM_OPEN: f64 = 0.4;
+
+// Where bilabial closures press on the default tract.
+const LIPS: f64 = 42.5;
+
+/// Phonemes keyed by IPA symbol.
+#[derive(Clone, Debug)]
+pub struct PhonemeInventory {
+    phonemes: HashMap<String, Phoneme>,
+}
+
+impl PhonemeInventory {
+    pub fn empty() -> Self {
+        PhonemeInventory {
+            phonemes: HashMap::new(),
+        }
+    }
+
+    /// The built-in inventory: common IPA vowels, nasals, stops, fricatives,
+    /// approximants and the tap, plus `_` for silence.
+    pub fn ipa() -> Self {
+        let mut inv = Self::empty();
+
+        // (symbol, tongue index, tongue diameter, lips)
+        let vowels = [
+            ("i", 12.0, 2.9, 1.5),
+            ("ɪ", 14.0, 2.5, 1.5),
+            ("e", 10.0, 2.6, 1.5),
+            ("ɛ", 8.0, 2.3, 1.5),
+            ("æ", 24.0, 2.6, 2.0),
+            ("a", 28.0, 2.9, 2.0),
+            ("ɑ", 30.0, 2.9, 2.0),
+            ("ʌ", 38.0, 2.4, 1.3),
+            ("ə", 26.0, 2.6, 1.5),
+            ("ɔ", 34.0, 2.6, 1.0),
+            ("o", 36.0, 2.6, 0.8),
+            ("ʊ", 40.0, 2.9, 1.0),
+            ("u", 40.0, 2.9, 0.5),
+            ("ɯ", 42.0, 2.6, 1.1),
+            ("y", 12.0, 2.9, 0.5),
+            ("ø", 10.0, 2.6, 0.6),
+        ];
+        for (symbol, index, diameter, lips) in vowels {
+            inv.insert(Phoneme {
+                symbol: symbol.to_string(),
+                manner: Manner::Vowel,
+                target: ArticulatoryTarget {
+                    tongue: Some((index, diameter)),
+                    lips: Some(lips),
+                    velum: Some(VELUM_CLOSED),
+                    frication: 0.0,
+                    ..Default::default()
+                },
+            });
+        }
+
+        // (symbol, manner, voiced, constriction index, constriction diameter)
+        let consonants = [
+            ("p", Manner::Stop, false, LIPS, 0.0),
+            ("b", Manner::Stop, true, LIPS, 0.0),
+            ("m", Manner::Nasal, true, LIPS, 0.0),
+            ("ɸ", Manner::Fricative, false, LIPS, 0.4),
+            ("β", Manner::Fricative, true, LIPS, 0.4),
+            ("t", Manner::Stop, false, 36.0, 0.0),
+            ("d", Manner::Stop, true, 36.0, 0.0),
+            ("k", Manner::Stop, false, 22.0, 0.0),
+            ("g", Manner::Stop, true, 22.0, 0.0),
+            ("n", Manner::Nasal, true, 36.0, 0.0),
+            ("ɲ", Manner::Nasal, true, 29.0, 0.0),
+            ("ŋ", Manner::Nasal, true, 22.0, 0.0),
+            ("ɴ", Manner::Nasal, true, 18.0, 0.0),
+            ("f", Manner::Fricative, false, 41.0, 0.45),
+            ("v", Manner::Fricative, true, 41.0, 0.45),
+            ("θ", Manner::Fricative, false, 38.5, 0.45),
+            ("ð", Manner::Fricative, true, 38.5, 0.45),
+            ("s", Manner::Fricative, false, 36.5, 0.42),
+            ("z", Manner::Fricative, true, 36.5, 0.42),
+            ("ʃ", Manner::Fricative, false, 34.0, 0.42),
+            ("ʒ", Manner::Fricative, true, 34.0, 0.42),
+            ("ɕ", Manner::Fricative, false, 32.0, 0.42),
+            ("ʑ", Manner::Fricative, true, 32.0, 0.42),
+            ("ç", Manner::Fricative, false, 29.0, 0.42),
+            ("x", Manner::Fricative, false, 22.0, 0.42),
+            ("ɣ", Manner::Fricative, true, 22.0, 0.42),
+            ("χ", Manner::Fricative, false, 18.0, 0.42),
+            ("ɾ", Manner::Tap, true, 36.0, 0.25),
+            ("l", Manner::Approximant, true, 35.0, 0.8),
+            ("ɹ", Manner::Approximant, true, 33.0, 0.9),
+            ("j", Manner::Approximant, true, 29.0, 0.9),
+        ];
+        for (symbol, manner, voiced, index, diameter) in consonants {
+            let velum = if manner == Manner::Nasal {
+                VELUM_OPEN
+            } else {
+                VELUM_CLOSED
+            };
+            inv.insert(Phoneme {
+                symbol: symbol.to_string(),
+                manner,
+                target: ArticulatoryTarget {
+                    velum: Some(velum),
+                    constrictions: vec![Constriction { index, diameter }],
+                    voiced,
+                    frication: if manner == Manner::Approximant {
+                        0.0
+                    } else {
+                        1.0
+                    },
+                    ..Default::default()
+                },
+            });
+        }
+
+        inv.insert(Phoneme {
+            symbol: "w".to_string(),
+            manner: Manner::Approximant,
+            target: ArticulatoryTarget {
+                velum: Some(VELUM_CLOSED),
+                constrictions: vec![
+                    Constriction {
+                        index: LIPS,
+                        diameter: 0.6,
+                    },
+                    Constriction {
+                        index: 22.0,
+                        diameter: 1.0,
+                    },
+                ],
+                frication: 0.0,
+                ..Default::default()
+            },
+        });
+        inv.insert(Phoneme {
+            symbol: "h".to_string(),
+            manner: Manner::Fricative,
+            target: ArticulatoryTarget {
+                velum: Some(VELUM_CLOSED),
+                voiced: false,
+                ..Default::default()
+            },
+        });
+        inv.insert(Phoneme {
+            symbol: "_".to_string(),
+            manner: Manner::Silence,
+            target: ArticulatoryTarget {
+                voiced: false,
+                breathing: false,
+                ..Default::default()
+            },
+        });
+
+        inv
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&Phoneme> {
+        self.phonemes.get(symbol)
+    }
+
+    /// Adds a phoneme, returning the one it replaces.
+    pub fn insert(&mut self, phoneme: Phoneme) -> Option<Phoneme> {
+        self.phonemes.insert(phoneme.symbol.clone(), phoneme)
+    }
+
+    pub fn remove(&mut self, symbol: &str) -> Option<Phoneme> {
+        self.phonemes.remove(symbol)
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.phonemes.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.phonemes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.phonemes.is_empty()
+    }
+}
+
+impl Default for PhonemeInventory {
+    fn default() -> Self {
+        Self::ipa()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voc::{test_voc, Frame};
+
+    #[test]
+    fn test_inventory_covers_manners() {
+        let inv = PhonemeInventory::ipa();
+        for (symbol, manner) in [
+            ("a", Manner::Vowel),
+            ("m", Manner::Nasal),
+            ("k", Manner::Stop),
+            ("s", Manner::Fricative),
+            ("j", Manner::Approximant),
+        ] {
+            assert_eq!(inv.get(symbol).unwrap().manner, manner);
+        }
+    }
+
+    #[test]
+    fn test_override() {
+        let mut inv = PhonemeInventory::ipa();
+        let mut a = inv.get("a").unwrap().clone();
+        a.target.tongue = Some((27.0, 3.0));
+        assert!(inv.insert(a).is_some());
+        assert_eq!(inv.get("a").unwrap().target.tongue, Some((27.0, 3.0)));
+    }
+
+    #[test]
+    fn test_stop_closes_the_tract() {
+        let inv = PhonemeInventory::ipa();
+        let mut v = test_voc();
+        v.apply_target(&inv.get("a").unwrap().target);
+        v.apply_target(&inv.get("t").unwrap().target);
+        assert_eq!(v.tract_diameters()[36], 0.0);
+        assert!(!v.is_voiced());
+
+        v.apply_target(&inv.get("m").unwrap().target);
+        assert!(v.tract_diameters()[36] > 1.0);
+        assert_eq!(v.tract_diameters()[42], 0.0);
+        assert_eq!(v.velum(), VELUM_OPEN);
+
+        v.apply_target(&inv.get("s").unwrap().target);
+        assert_eq!(v.tract_diameters()[42], v.lips());
+    }
+
+    #[test]
+    fn test_vowels_do_not_hiss() {
+        let inv = PhonemeInventory::ipa();
+        let mut frames = vec![Frame::default(); 4096];
+        for (symbol, hisses) in [("a", false), ("u", false), ("w", false), ("s", true)] {
+            let mut v = test_voc();
+            v.apply_target(&inv.get(symbol).unwrap().target);
+            v.process_frames(&mut frames);
+            assert_eq!(frames.iter().any(|f| f.noise != 0.0), hisses, "{}", symbol);
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::VocConfig;
+
+/// Segment counts and landmarks of a tract, as in `VocConfig`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Geometry {
+    pub n: usize,
+    pub nose_length: usize,
+    pub nose_start: usize,
+    pub tip_start: usize,
+    pub blade_start: usize,
+    pub epiglottis_start: usize,
+    pub lip_start: usize,
+}
+
+impl Geometry {
+    pub fn of(config: &VocConfig) -> Self {
+        Geometry {
+            n: config.n,
+            nose_length: config.nose_length,
+            nose_start: config.nose_start,
+            tip_start: config.tip_start,
+            blade_start: config.blade_start,
+            epiglottis_start: config.epiglottis_start,
+            lip_start: config.lip_start,
+        }
+    }
+}
+
+/// The controllable parameters of a `Voc`: what a vowel or consonant preset
+/// stores. Taken with `Voc::state` and restored with `Voc::apply_preset`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VocState {
+    pub frequency: f64,
+    pub tenseness: f64,
+    pub velum: f64,
+    pub tongue_index: f64,
+    pub tongue_diameter: f64,
+    pub lips: f64,
+    pub target_diameter: Vec<f64>,
+    pub nose_diameter: Vec<f64>,
+    pub geometry: Geometry,
+}
+
+impl VocState {
+    pub fn to_json(&self) -> Result<String, PresetError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, PresetError> {
+        Ok(serde_json::from_str(s)?)
+    }
+
+    pub fn to_toml(&self) -> Result<String, PresetError> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, PresetError> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// Reads a preset, choosing the format from the `.json` or `.toml`
+    /// extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PresetError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        match extension(path)? {
+            Format::Json => Self::from_json(&text),
+            Format::Toml => Self::from_toml(&text),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PresetError> {
+        let path = path.as_ref();
+        let text = match extension(path)? {
+            Format::Json => self.to_json()?,
+            Format::Toml => self.to_toml()?,
+        };
+        Ok(fs::write(path, text)?)
+    }
+}
+
+enum Format {
+    Json,
+    Toml,
+}
+
+fn extension(path: &Path) -> Result<Format, PresetError> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => Ok(Format::Json),
+        Some("toml") => Ok(Format::Toml),
+        _ => Err(PresetError::UnknownFormat(path.display().to_string())),
+    }
+}
+
+#[derive(Debug)]
+pub enum PresetError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    TomlRead(toml::de::Error),
+    TomlWrite(toml::ser::Error),
+    UnknownFormat(String),
+    /// The preset was made for a tract of a different shape.
+    GeometryMismatch {
+        expected: Geometry,
+        found: Geometry,
+    },
+}
+
+impl fmt::Display for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PresetError::Io(e) => write!(f, "could not access preset: {}", e),
+            PresetError::Json(e) => write!(f, "invalid JSON preset: {}", e),
+            PresetError::TomlRead(e) => write!(f, "invalid TOML preset: {}", e),
+            PresetError::TomlWrite(e) => write!(f, "could not write TOML preset: {}", e),
+            PresetError::UnknownFormat(path) => {
+                write!(f, "{} is neither a .json nor a .toml file", path)
+            }
+            PresetError::GeometryMismatch { expected, found } => write!(
+                f,
+                "preset is for a {}-segment tract with a {}-segment nose, voice has {} and {}",
+                found.n, found.nose_length, expected.n, expected.nose_length
+            ),
+        }
+    }
+}
+
+impl Error for PresetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PresetError::Io(e) => Some(e),
+            PresetError::Json(e) => Some(e),
+            PresetError::TomlRead(e) => Some(e),
+            PresetError::TomlWrite(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PresetError {
+    fn from(e: io::Error) -> Self {
+        PresetError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for PresetError {
+    fn from(e: serde_json::Error) -> Self {
+        PresetError::Json(e)
+    }
+}
+
+impl From<toml::de::Error> for PresetError {
+    fn from(e: toml::de::Error) -> Self {
+        PresetError::TomlRead(e)
+    }
+}
+
+impl From<toml::ser::Error> for PresetError {
+    fn from(e: toml::ser::Error) -> Self {
+        PresetError::TomlWrite(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phoneme::PhonemeInventory;
+    use crate::voc::{test_voc, Voc};
+
+    fn shaped() -> VocState {
+        let mut v = test_voc();
+        v.apply_target(&PhonemeInventory::ipa().get("ʃ").unwrap().target);
+        v.set_frequency(180.5);
+        v.set_velum(0.2);
+        v.state()
+    }
+
+    #[test]
+    fn test_json_and_toml_round_trip() {
+        let state = shaped();
+        assert_eq!(
+            VocState::from_json(&state.to_json().unwrap()).unwrap(),
+            state
+        );
+        assert_eq!(
+            VocState::from_toml(&state.to_toml().unwrap()).unwrap(),
+            state
+        );
+    }
+
+    #[test]
+    fn test_apply_preset() {
+        let state = shaped();
+        let mut v = test_voc();
+        v.apply_preset(&state).unwrap();
+        assert_eq!(v.state(), state);
+
+        // The velum opens gradually instead of jumping to the stored shape.
+        let mut opened = state.clone();
+        opened.nose_diameter[0] = 0.3;
+        let mut v = test_voc();
+        v.apply_preset(&opened).unwrap();
+        assert_eq!(v.nose_diameters()[0], 0.01);
+        v.process(&mut [0.0; 4096]);
+        let velum = v.nose_diameters()[0];
+        assert!(velum > 0.01 && velum <= 0.2, "{}", velum);
+
+        let mut short =
+            Voc::from_config(VocConfig::builder().n(40).lip_start(35).build().unwrap()).unwrap();
+        assert!(matches!(
+            short.apply_preset(&state),
+            Err(PresetError::GeometryMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let state = shaped();
+        let dir = std::env::temp_dir();
+        for ext in ["json", "toml"] {
+            let name = format!("pinktrombone-{}-save-and-load.{}", std::process::id(), ext);
+            let path = dir.join(name);
+            state.save(&path).unwrap();
+            assert_eq!(VocState::load(&path).unwrap(), state);
+            fs::remove_file(path).unwrap();
+        }
+        assert!(matches!(
+            state.save(dir.join("preset.yaml")),
+            Err(PresetError::UnknownFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,353 @@
+use crate::automation::{Automation, Curve, Param};
+use crate::float::Float;
+use crate::kana::{parse, KanaError, Mora};
+use crate::phoneme::{ArticulatoryTarget, PhonemeInventory};
+use crate::sequence::{to_samples, Sequence};
+use crate::voc::Voc;
+
+/// Notes closer than this are sung legato.
+const LEGATO_GAP: f64 = 0.02;
+/// Time the vibrato takes to reach full depth once it starts.
+const VIBRATO_SWELL: f64 = 0.3;
+/// Longest a trailing mora such as ん takes out of its note.
+const TAIL_MORA: f64 = 0.12;
+
+type Timeline = Vec<(f64, ArticulatoryTarget)>;
+
+pub fn midi_to_frequency(note: f64) -> f64 {
+    440.0 * 2f64.powf((note - 69.0) / 12.0)
+}
+
+/// A sung note. `pitch` is a MIDI note number and may be fractional, times
+/// are in seconds. The lyric is kana; an empty lyric, `-` or `ー` carries the
+/// previous vowel over to this note.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Note {
+    pub pitch: f64,
+    pub start: f64,
+    pub duration: f64,
+    pub lyric: String,
+}
+
+impl Note {
+    pub fn new(pitch: f64, start: f64, duration: f64, lyric: &str) -> Self {
+        Note {
+            pitch,
+            start,
+            duration,
+            lyric: lyric.to_string(),
+        }
+    }
+
+    pub fn end(&self) -> f64 {
+        self.start + self.duration
+    }
+
+    pub fn frequency(&self) -> f64 {
+        midi_to_frequency(self.pitch)
+    }
+
+    /// The lyric split into moras; empty for a continuation.
+    pub fn moras(&self) -> Result<Vec<Mora>, KanaError> {
+        if self.is_continuation() {
+            return Ok(Vec::new());
+        }
+        parse(&self.lyric)
+    }
+
+    fn is_continuation(&self) -> bool {
+        matches!(self.lyric.trim(), "" | "-" | "ー")
+    }
+}
+
+/// A monophonic vocal line rendered through the automation of a `Voc`.
+///
+/// Vowels land on the beat with the syllable's consonants placed just
+/// before it. Notes that touch glide into each other over the portamento
+/// time; notes held past the vibrato delay swell into vibrato.
+#[derive(Clone, Debug)]
+pub struct Score {
+    notes: Vec<Note>,
+    pub inventory: PhonemeInventory,
+    /// Length of a pitch glide between legato notes in seconds.
+    pub portamento: f64,
+    /// Time into a note before vibrato starts.
+    pub vibrato_delay: f64,
+    /// Vibrato depth as a fraction of the frequency.
+    pub vibrato_depth: f64,
+    pub vibrato_rate: f64,
+    /// Length of each consonant sung ahead of the beat.
+    pub consonant_time: f64,
+    /// Silence rendered after the last note so the voice can die away.
+    pub release: f64,
+}
+
+impl Score {
+    pub fn new() -> Self {
+        Score {
+            notes: Vec::new(),
+            inventory: PhonemeInventory::ipa(),
+            portamento: 0.08,
+            vibrato_delay: 0.3,
+            vibrato_depth: 0.012,
+            vibrato_rate: 5.5,
+            consonant_time: 0.06,
+            release: 0.2,
+        }
+    }
+
+    /// Adds a note, keeping the score in start order.
+    pub fn push(&mut self, note: Note) {
+        let at = self.notes.partition_point(|n| n.start <= note.start);
+        self.notes.insert(at, note);
+    }
+
+    pub fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+
+    /// Time before zero that rendering starts at, leaving room for the first
+    /// note's consonants.
+    pub fn lead_in(&self) -> f64 {
+        2.0 * self.consonant_time
+    }
+
+    /// Length of the rendered output in seconds, lead-in and release included.
+    pub fn duration(&self) -> f64 {
+        let end = self.notes.iter().map(Note::end).fold(0.0, f64::max);
+        self.lead_in() + end + self.release
+    }
+
+    fn target(&self, symbol: &'static str) -> Result<ArticulatoryTarget, KanaError> {
+        self.inventory
+            .get(symbol)
+            .map(|p| p.target.clone())
+            .ok_or(KanaError::MissingPhoneme(symbol))
+    }
+
+    // Articulatory targets with their start times in score seconds, and the
+    // time each note's first consonant starts.
+    fn timeline(&self) -> Result<(Timeline, Vec<f64>), KanaError> {
+        let mut onsets = Vec::with_capacity(self.notes.len());
+        let mut timeline = vec![(-self.lead_in(), self.target("_")?)];
+        let push = |timeline: &mut Timeline, time: f64, target| {
+            let last = timeline.last().map_or(time, |&(t, _)| t);
+            timeline.push((time.max(last), target));
+        };
+
+        let mut prev: Option<&Note> = None;
+        for note in &self.notes {
+            if note.is_continuation() {
+                onsets.push(note.start);
+                prev = Some(note);
+                continue;
+            }
+            let moras = note.moras()?;
+            let Some((first, tail)) = moras.split_first() else {
+                onsets.push(note.start);
+                continue;
+            };
+
+            // A mora without a vowel, like ん, is sustained on the beat.
+            let (onset_consonants, on_beat): (&[&'static str], Vec<&'static str>) =
+                match first.vowel {
+                    Some(vowel) => (&first.consonant, vec![vowel]),
+                    None => (&[], first.consonant.clone()),
+                };
+
+            let last = timeline.last().map_or(note.start, |&(t, _)| t);
+            let consonants = onset_consonants.len() as f64;
+            let lead = (consonants * self.consonant_time).min((note.start - last).max(0.0) / 2.0);
+            let onset = note.start - lead;
+            onsets.push(onset);
+
+            if let Some(p) = prev {
+                if p.end() < onset {
+                    push(&mut timeline, p.end(), self.target("_")?);
+                }
+            }
+            for (k, &symbol) in onset_consonants.iter().enumerate() {
+                let time = onset + lead * k as f64 / consonants;
+                push(&mut timeline, time, self.target(symbol)?);
+            }
+            for &symbol in &on_beat {
+                push(&mut timeline, note.start, self.target(symbol)?);
+            }
+
+            // Later moras share the end of the note.
+            let each = TAIL_MORA.min(note.duration / (2.0 * tail.len() as f64));
+            let tail_start = note.end() - each * tail.len() as f64;
+            for (j, mora) in tail.iter().enumerate() {
+                let phonemes: Vec<&'static str> = mora.phonemes().collect();
+                let step = each / phonemes.len() as f64;
+                for (k, symbol) in phonemes.into_iter().enumerate() {
+                    let time = tail_start + each * j as f64 + step * k as f64;
+                    push(&mut timeline, time, self.target(symbol)?);
+                }
+            }
+
+            prev = Some(note);
+        }
+
+        if let Some(p) = prev {
+            push(&mut timeline, p.end(), self.target("_")?);
+        }
+        Ok((timeline, onsets))
+    }
+
+    /// Turns the score into automation for `voc`, starting the lead-in at its
+    /// current sample time.
+    pub fn compile<F: Float>(&self, voc: &Voc<F>) -> Result<Automation, KanaError> {
+        let (timeline, onsets) = self.timeline()?;
+        let end = self.duration() - self.lead_in();
+        let mut seq = Sequence::new();
+        seq.set_transition(0.04);
+        for (i, (time, target)) in timeline.iter().enumerate() {
+            let next = timeline.get(i + 1).map_or(end, |&(t, _)| t);
+            seq.push(target.clone(), next - time, voc.frequency());
+        }
+
+        let mut automation = seq.compile(voc);
+        automation.remove(Param::Frequency);
+
+        let sr = voc.sr;
+        let origin = voc.sample_time();
+        let lead_in = self.lead_in();
+        let at = |seconds: f64| origin + to_samples(seconds + lead_in, sr);
+
+        automation.set(Param::VibratoRate, origin, self.vibrato_rate);
+        let mut prev: Option<&Note> = None;
+        for (note, &onset) in self.notes.iter().zip(&onsets) {
+            let f = note.frequency();
+            match prev {
+                None => automation.set(Param::Frequency, origin, f),
+                Some(p) if note.start - p.end() <= LEGATO_GAP => {
+                    let half = (self.portamento / 2.0)
+                        .min(p.duration / 2.0)
+                        .min(note.duration / 2.0);
+                    automation.add(
+                        Param::Frequency,
+                        at(note.start - half),
+                        p.frequency(),
+                        Curve::Step,
+                    );
+                    automation.ramp(
+                        Param::Frequency,
+                        at(note.start + half),
+                        f,
+                        Curve::Exponential,
+                    );
+                }
+                // Detached notes jump as their consonants start.
+                Some(p) => automation.set(Param::Frequency, at(onset.max(p.end())), f),
+            }
+
+            automation.set(Param::VibratoDepth, at(note.start), 0.0);
+            let swell = note.start + self.vibrato_delay;
+            if swell < note.end() {
+                automation.set(Param::VibratoDepth, at(swell), 0.0);
+                let full = (swell + VIBRATO_SWELL).min(note.end());
+                automation.ramp(
+                    Param::VibratoDepth,
+                    at(full),
+                    self.vibrato_depth,
+                    Curve::Linear,
+                );
+            }
+
+            prev = Some(note);
+        }
+
+        Ok(automation)
+    }
+
+    /// Renders the score from the voice's current position, replacing its
+    /// automation. The output starts `lead_in` seconds before score time 0.
+    pub fn render(&self, voc: &mut Voc) -> Result<Vec<f64>, KanaError> {
+        let automation = self.compile(voc)?;
+        voc.set_automation(automation);
+        let mut out = vec![0.0; to_samples(self.duration(), voc.sr) as usize];
+        voc.process(&mut out);
+        Ok(out)
+    }
+}
+
+impl Default for Score {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voc::test_voc;
+
+    fn score(notes: &[(f64, f64, f64, &str)]) -> Score {
+        let mut s = Score::new();
+        for &(pitch, start, duration, lyric) in notes {
+            s.push(Note::new(pitch, start, duration, lyric));
+        }
+        s
+    }
+
+    #[test]
+    fn test_consonant_closes_before_the_beat() {
+        let s = score(&[(60.0, 0.0, 0.5, "あ"), (62.0, 0.5, 0.5, "か")]);
+        let v = test_voc();
+        let a = s.compile(&v).unwrap();
+        let at = |seconds: f64| to_samples(seconds + s.lead_in(), v.sr);
+
+        let velar = |seconds| a.value_at(Param::Diameter(22), at(seconds)).unwrap();
+        assert!(velar(0.5 - s.consonant_time / 2.0) < 0.1);
+        assert!(velar(0.3) > 1.0);
+        assert!(velar(0.7) > 1.0);
+        assert_eq!(a.value_at(Param::Voicing, at(0.5)), Some(1.0));
+    }
+
+    #[test]
+    fn test_legato_glides_and_vibrato_swells() {
+        let s = score(&[(60.0, 0.0, 1.0, "ら"), (72.0, 1.0, 0.2, "ー")]);
+        let v = test_voc();
+        let a = s.compile(&v).unwrap();
+        let at = |seconds: f64| to_samples(seconds + s.lead_in(), v.sr);
+
+        let f = |seconds| a.value_at(Param::Frequency, at(seconds)).unwrap();
+        let low = midi_to_frequency(60.0);
+        let high = midi_to_frequency(72.0);
+        assert!((f(0.5) - low).abs() < 1e-9);
+        assert!((f(1.0) - (low * high).sqrt()).abs() < 1.0);
+        assert!((f(1.1) - high).abs() < 1e-9);
+
+        let depth = |seconds| a.value_at(Param::VibratoDepth, at(seconds)).unwrap();
+        assert_eq!(depth(0.2), 0.0);
+        assert!((depth(0.95) - s.vibrato_depth).abs() < 1e-12);
+        assert_eq!(depth(1.1), 0.0);
+        assert_eq!(
+            a.value_at(Param::VibratoRate, at(0.5)),
+            Some(s.vibrato_rate)
+        );
+    }
+
+    #[test]
+    fn test_detached_note_changes_pitch_with_its_consonant() {
+        let s = score(&[(60.0, 0.0, 0.3, "あ"), (64.0, 0.5, 0.3, "か")]);
+        let v = test_voc();
+        let a = s.compile(&v).unwrap();
+        let at = |seconds: f64| to_samples(seconds + s.lead_in(), v.sr);
+
+        let f = |seconds| a.value_at(Param::Frequency, at(seconds)).unwrap();
+        let onset = 0.5 - s.consonant_time;
+        assert_eq!(f(onset - 0.01), midi_to_frequency(60.0));
+        assert_eq!(f(onset), midi_to_frequency(64.0));
+    }
+
+    #[test]
+    fn test_render_length() {
+        let s = score(&[(57.0, 0.0, 0.3, "さ"), (59.0, 0.5, 0.3, "くら")]);
+        let mut v = test_voc();
+        let out = s.render(&mut v).unwrap();
+        assert_eq!(out.len(), to_samples(s.duration(), v.sr) as usize);
+        assert!(out.iter().all(|x| x.is_finite()));
+    }
+}
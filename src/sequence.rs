@@ -0,0 +1,300 @@
+use std::path::Path;
+
+use crate::automation::{Automation, Curve, Param};
+use crate::float::Float;
+use crate::phoneme::ArticulatoryTarget;
+use crate::voc::{shape_target, Voc};
+
+/// One entry of a `Sequence`: `target` held for `duration` seconds, sung at
+/// `pitch` Hz.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gesture {
+    pub target: ArticulatoryTarget,
+    pub duration: f64,
+    pub pitch: f64,
+}
+
+/// Timed articulatory targets, rendered through the automation of a `Voc`.
+///
+/// Neighbouring gestures overlap: the tract glides from one target to the
+/// next over a transition window centred on their boundary. Articulators a
+/// gesture leaves unset are taken from the next gesture that sets them, so
+/// the tongue and lips already head for the coming vowel during a consonant.
+#[derive(Clone, Debug)]
+pub struct Sequence {
+    gestures: Vec<Gesture>,
+    transition: f64,
+}
+
+// The tract and glottis settings one gesture resolves to.
+struct Pose {
+    diameters: Vec<f64>,
+    velum: f64,
+    tenseness: f64,
+    voiced: bool,
+    breathing: bool,
+    frication: f64,
+    pitch: f64,
+}
+
+impl Pose {
+    fn write(&self, automation: &mut Automation, time: u64, curve: Curve) {
+        for (i, &d) in self.diameters.iter().enumerate() {
+            automation.add(Param::Diameter(i), time, d, curve);
+        }
+        automation.add(Param::Velum, time, self.velum, curve);
+        automation.add(Param::Tenseness, time, self.tenseness, curve);
+        let pitch_curve = match curve {
+            Curve::Linear => Curve::Exponential,
+            other => other,
+        };
+        automation.add(Param::Frequency, time, self.pitch, pitch_curve);
+    }
+}
+
+pub(crate) fn to_samples(seconds: f64, sr: f64) -> u64 {
+    (seconds.max(0.0) * sr).round() as u64
+}
+
+// Takes the value from `slot` into `carry`, or fills an empty slot from it.
+fn fill<T: Copy>(slot: &mut Option<T>, carry: &mut Option<T>) {
+    match *slot {
+        Some(v) => *carry = Some(v),
+        None => *slot = *carry,
+    }
+}
+
+impl Sequence {
+    pub fn new() -> Self {
+        Sequence {
+            gestures: Vec::new(),
+            transition: 0.05,
+        }
+    }
+
+    pub fn push(&mut self, target: ArticulatoryTarget, duration: f64, pitch: f64) {
+        self.gestures.push(Gesture {
+            target,
+            duration,
+            pitch,
+        });
+    }
+
+    pub fn gestures(&self) -> &[Gesture] {
+        &self.gestures
+    }
+
+    pub fn transition(&self) -> f64 {
+        self.transition
+    }
+
+    /// Sets the length of the glide between neighbouring targets in seconds.
+    /// Each window is clipped to half of the shorter gesture around it.
+    pub fn set_transition(&mut self, seconds: f64) {
+        self.transition = seconds.max(0.0);
+    }
+
+    /// Total length in seconds.
+    pub fn duration(&self) -> f64 {
+        self.gestures.iter().map(|g| g.duration).sum()
+    }
+
+    pub fn len(&self) -> usize {
+        self.gestures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.gestures.is_empty()
+    }
+
+    // Fills unset articulators from the next gesture that sets them.
+    fn resolve(&self) -> Vec<ArticulatoryTarget> {
+        let mut targets: Vec<ArticulatoryTarget> =
+            self.gestures.iter().map(|g| g.target.clone()).collect();
+        let (mut tongue, mut lips, mut velum, mut tenseness) = (None, None, None, None);
+        for target in targets.iter_mut().rev() {
+            fill(&mut target.tongue, &mut tongue);
+            fill(&mut target.lips, &mut lips);
+            fill(&mut target.velum, &mut velum);
+            fill(&mut target.tenseness, &mut tenseness);
+        }
+        targets
+    }
+
+    /// Turns the sequence into automation for `voc`, starting at its current
+    /// sample time. Targets are resolved against the voice's geometry and
+    /// its present tract, tongue, lips, velum and tenseness; `voc` itself is
+    /// left untouched.
+    pub fn compile<F: Float>(&self, voc: &Voc<F>) -> Automation {
+        let mut automation = Automation::new();
+
+        let mut diameters = voc.tract_diameters().to_vec();
+        let mut tongue = (voc.tongue_index(), voc.tongue_diameter());
+        let mut lips = voc.lips();
+        let (mut velum, mut tenseness) = (voc.velum(), voc.tenseness());
+
+        let sr = voc.sr;
+        let mut time = voc.sample_time();
+        let mut prev: Option<(Pose, u64)> = None;
+        for (gesture, target) in self.gestures.iter().zip(self.resolve()) {
+            shape_target(
+                voc.config(),
+                &mut diameters,
+                &target,
+                &mut tongue,
+                &mut lips,
+            );
+            velum = target.velum.unwrap_or(velum);
+            tenseness = target.tenseness.unwrap_or(tenseness);
+            let pose = Pose {
+                diameters: diameters.clone(),
+                velum,
+                tenseness,
+                voiced: target.voiced,
+                breathing: target.breathing,
+                frication: target.frication,
+                pitch: gesture.pitch,
+            };
+            let len = to_samples(gesture.duration, sr);
+
+            match &prev {
+                None => pose.write(&mut automation, time, Curve::Step),
+                Some((prev_pose, prev_len)) => {
+                    let half = to_samples(self.transition / 2.0, sr)
+                        .min(prev_len / 2)
+                        .min(len / 2);
+                    prev_pose.write(&mut automation, time - half, Curve::Linear);
+                    pose.write(&mut automation, time + half, Curve::Linear);
+                }
+            }
+            automation.set(Param::Voicing, time, if pose.voiced { 1.0 } else { 0.0 });
+            automation.set(
+                Param::Breathing,
+                time,
+                if pose.breathing { 1.0 } else { 0.0 },
+            );
+            automation.set(Param::FricativeIntensity, time, pose.frication);
+
+            time += len;
+            prev = Some((pose, len));
+        }
+
+        automation
+    }
+
+    /// Renders the sequence from the voice's current position, replacing its
+    /// automation.
+    pub fn render(&self, voc: &mut Voc) -> Vec<f64> {
+        let len: u64 = self
+            .gestures
+            .iter()
+            .map(|g| to_samples(g.duration, voc.sr))
+            .sum();
+        voc.set_automation(self.compile(voc));
+        let mut out = vec![0.0; len as usize];
+        voc.process(&mut out);
+        out
+    }
+
+    /// Renders the sequence into a mono 32-bit float WAV file.
+    pub fn write_wav<P: AsRef<Path>>(&self, voc: &mut Voc, path: P) -> Result<(), hound::Error> {
+        let samples = self.render(voc);
+        let mut writer = hound::WavWriter::create(
+            path,
+            hound::WavSpec {
+                channels: 1,
+                sample_rate: voc.sr as u32,
+                bits_per_sample: 32,
+                sample_format: hound::SampleFormat::Float,
+            },
+        )?;
+        for &sample in &samples {
+            writer.write_sample(sample as f32)?;
+        }
+        writer.finalize()
+    }
+}
+
+impl Default for Sequence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phoneme::PhonemeInventory;
+    use crate::voc::test_voc;
+
+    fn word(symbols: &[&str]) -> Sequence {
+        let inv = PhonemeInventory::ipa();
+        let mut seq = Sequence::new();
+        for s in symbols {
+            seq.push(inv.get(s).unwrap().target.clone(), 0.1, 140.0);
+        }
+        seq
+    }
+
+    #[test]
+    fn test_consonants_blend_into_neighbours() {
+        let seq = word(&["a", "t", "i"]);
+        let v = test_voc();
+        let a = seq.compile(&v);
+        let sr = v.sr;
+        let at = |seconds: f64, i: usize| a.value_at(Param::Diameter(i), (seconds * sr) as u64);
+
+        // Closed in the middle of the stop, half way there at the boundary.
+        assert_eq!(at(0.15, 36), Some(0.0));
+        let open = at(0.05, 36).unwrap();
+        let boundary = at(0.1, 36).unwrap();
+        assert!(
+            (boundary - open / 2.0).abs() < 0.02,
+            "{} {}",
+            open,
+            boundary
+        );
+
+        // The tongue body already sits in /i/ during the stop.
+        assert_eq!(at(0.15, 14), at(0.25, 14));
+        assert_ne!(at(0.05, 14), at(0.25, 14));
+
+        assert_eq!(a.value_at(Param::Voicing, (0.15 * sr) as u64), Some(0.0));
+    }
+
+    #[test]
+    fn test_render_covers_the_sequence() {
+        let seq = word(&["m", "a", "s", "a"]);
+        let mut v = test_voc();
+        let out = seq.render(&mut v);
+        assert_eq!(out.len(), (seq.duration() * v.sr).round() as usize);
+        assert!(out.iter().all(|x| x.is_finite()));
+        assert!(out.iter().any(|&x| x.abs() > 1e-3));
+    }
+
+    fn rms(samples: &[f64]) -> f64 {
+        (samples.iter().map(|x| x * x).sum::<f64>() / samples.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn test_frication_follows_the_targets() {
+        let seq = word(&["a", "s", "a"]);
+        let v = test_voc();
+        let a = seq.compile(&v);
+        let at = |seconds: f64| a.value_at(Param::FricativeIntensity, (seconds * v.sr) as u64);
+        assert_eq!(at(0.05), Some(0.0));
+        assert_eq!(at(0.15), Some(1.0));
+        assert_eq!(at(0.25), Some(0.0));
+    }
+
+    #[test]
+    fn test_breath_flows_through_h() {
+        let mut v = test_voc();
+        v.set_voice_release(0.01);
+        let out = word(&["_", "h", "_"]).render(&mut v);
+        let third = out.len() / 3;
+        let h = rms(&out[third + third / 2..2 * third]);
+        let silence = rms(&out[2 * third + third / 2..]);
+        assert!(h > 1e-4 && h > 20.0 * silence, "{} {}", h, silence);
+    }
+}
@@ -0,0 +1,305 @@
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Shape of one glottal pulse. `Glottis` handles pitch, intensity and noise
+/// and asks the source for the excitation at each point of the period.
+///
+/// Implementations return the glottal flow derivative, normalized so that the
+/// main excitation peak reaches -1.
+pub trait GlottalSource: Send {
+    /// Prepares the pulse for a new period. `tenseness` runs from 0 (lax,
+    /// breathy) to 1 (pressed).
+    fn setup(&mut self, tenseness: f64);
+
+    /// Returns the excitation at normalized phase `t` in `[0, 1)`.
+    fn compute(&self, t: f64) -> f64;
+
+    /// Normalized phase of the glottal closure instant, the main excitation
+    /// of the period.
+    fn closure_instant(&self) -> f64;
+
+    /// Copies the source, so a `Voc` can be snapshotted with it.
+    fn clone_box(&self) -> Box<dyn GlottalSource>;
+}
+
+impl Clone for Box<dyn GlottalSource> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Liljencrants-Fant model, parameterized by Rd.
+#[derive(Clone)]
+pub struct LfModel {
+    alpha: f64,
+    e0: f64,
+    epsilon: f64,
+    shift: f64,
+    delta: f64,
+    te: f64,
+    omega: f64,
+}
+
+impl LfModel {
+    pub fn new() -> Self {
+        LfModel {
+            alpha: 0.0,
+            e0: 0.0,
+            epsilon: 0.0,
+            shift: 0.0,
+            delta: 0.0,
+            te: 0.0,
+            omega: 0.0,
+        }
+    }
+}
+
+impl Default for LfModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlottalSource for LfModel {
+    fn setup(&mut self, tenseness: f64) {
+        let rd = (3.0 * (1.0 - tenseness)).clamp(0.5, 2.7);
+
+        let ra = -0.01 + 0.048 * rd;
+        let rk = 0.224 + 0.118 * rd;
+        let rg = (rk / 4.0) * (0.5 + 1.2 * rk) / (0.11 * rd - ra * (0.5 + 1.2 * rk));
+
+        let ta = ra;
+        let tp = 1.0 / (2.0 * rg);
+        let te = tp + tp * rk;
+
+        self.epsilon = 1.0 / ta;
+        self.shift = (-self.epsilon * (1.0 - te)).exp();
+        self.delta = 1.0 - self.shift;
+
+        let mut rhs_integral = (1.0 / self.epsilon) * (self.shift - 1.0) + (1.0 - te) * self.shift;
+        rhs_integral /= self.delta;
+        let lower_integral = -(te - tp) / 2.0 + rhs_integral;
+        let upper_integral = -lower_integral;
+
+        self.omega = PI / tp;
+        let s = (self.omega * te).sin();
+
+        let y = -PI * s * upper_integral / (tp * 2.0);
+        let z = y.ln();
+        self.alpha = z / (tp / 2.0 - te);
+        self.e0 = -1.0 / (s * (self.alpha * te).exp());
+
+        self.te = te;
+    }
+
+    fn compute(&self, t: f64) -> f64 {
+        if t > self.te {
+            (-(-self.epsilon * (t - self.te)).exp() + self.shift) / self.delta
+        } else {
+            self.e0 * (self.alpha * t).exp() * (self.omega * t).sin()
+        }
+    }
+
+    fn closure_instant(&self) -> f64 {
+        self.te
+    }
+
+    fn clone_box(&self) -> Box<dyn GlottalSource> {
+        Box::new(self.clone())
+    }
+}
+
+// Open quotient shared by the polynomial and trigonometric models.
+fn open_quotient(tenseness: f64) -> f64 {
+    (0.85 - 0.45 * tenseness).clamp(0.3, 0.95)
+}
+
+/// Rosenberg trigonometric pulse (type C): a raised-cosine opening phase
+/// followed by a quarter-cosine closing phase.
+#[derive(Clone)]
+pub struct Rosenberg {
+    tp: f64,
+    tn: f64,
+}
+
+impl Rosenberg {
+    pub fn new() -> Self {
+        Rosenberg { tp: 0.0, tn: 0.0 }
+    }
+}
+
+impl Default for Rosenberg {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlottalSource for Rosenberg {
+    fn setup(&mut self, tenseness: f64) {
+        let oq = open_quotient(tenseness);
+        self.tp = oq * 2.0 / 3.0;
+        self.tn = oq / 3.0;
+    }
+
+    fn compute(&self, t: f64) -> f64 {
+        if t < self.tp {
+            (self.tn / self.tp) * (PI * t / self.tp).sin()
+        } else if t < self.tp + self.tn {
+            -(PI * (t - self.tp) / (2.0 * self.tn)).sin()
+        } else {
+            0.0
+        }
+    }
+
+    fn closure_instant(&self) -> f64 {
+        self.tp + self.tn
+    }
+
+    fn clone_box(&self) -> Box<dyn GlottalSource> {
+        Box::new(self.clone())
+    }
+}
+
+/// KLGLOTT88 polynomial pulse (Klatt & Klatt 1990): a cubic flow during the
+/// open phase with an abrupt closure.
+#[derive(Clone)]
+pub struct Klglott88 {
+    oq: f64,
+}
+
+impl Klglott88 {
+    pub fn new() -> Self {
+        Klglott88 { oq: 0.0 }
+    }
+}
+
+impl Default for Klglott88 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlottalSource for Klglott88 {
+    fn setup(&mut self, tenseness: f64) {
+        self.oq = open_quotient(tenseness);
+    }
+
+    fn compute(&self, t: f64) -> f64 {
+        if t < self.oq {
+            let x = t / self.oq;
+            x * (2.0 - 3.0 * x)
+        } else {
+            0.0
+        }
+    }
+
+    fn closure_instant(&self) -> f64 {
+        self.oq
+    }
+
+    fn clone_box(&self) -> Box<dyn GlottalSource> {
+        Box::new(self.clone())
+    }
+}
+
+/// Plays back one recorded or designed period with linear interpolation.
+/// Tenseness has no effect, and the closure instant is taken at the most
+/// negative sample.
+#[derive(Clone)]
+pub struct Wavetable {
+    table: Vec<f64>,
+    closure: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WavetableError {
+    Empty,
+}
+
+impl fmt::Display for WavetableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WavetableError::Empty => write!(f, "wavetable must hold at least one sample"),
+        }
+    }
+}
+
+impl Error for WavetableError {}
+
+impl Wavetable {
+    /// `table` holds one period of the excitation.
+    pub fn new(table: Vec<f64>) -> Result<Self, WavetableError> {
+        if table.is_empty() {
+            return Err(WavetableError::Empty);
+        }
+        let min = table
+            .iter()
+            .enumerate()
+            .fold(0, |min, (i, &x)| if x < table[min] { i } else { min });
+        let closure = min as f64 / table.len() as f64;
+        Ok(Wavetable { table, closure })
+    }
+}
+
+impl GlottalSource for Wavetable {
+    fn setup(&mut self, _tenseness: f64) {}
+
+    fn compute(&self, t: f64) -> f64 {
+        let len = self.table.len();
+        let pos = t.rem_euclid(1.0) * len as f64;
+        let i = (pos as usize).min(len - 1);
+        let frac = pos - i as f64;
+        self.table[i] * (1.0 - frac) + self.table[(i + 1) % len] * frac
+    }
+
+    fn closure_instant(&self) -> f64 {
+        self.closure
+    }
+
+    fn clone_box(&self) -> Box<dyn GlottalSource> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn period(source: &mut dyn GlottalSource, tenseness: f64) -> Vec<f64> {
+        source.setup(tenseness);
+        (0..1000)
+            .map(|i| source.compute(i as f64 / 1000.0))
+            .collect()
+    }
+
+    #[test]
+    fn test_models_peak_at_minus_one_and_return_to_zero_flow() {
+        let models: Vec<Box<dyn GlottalSource>> = vec![
+            Box::new(LfModel::new()),
+            Box::new(Rosenberg::new()),
+            Box::new(Klglott88::new()),
+        ];
+        for mut model in models {
+            for tenseness in [0.2, 0.6, 0.9] {
+                let p = period(model.as_mut(), tenseness);
+                let min = p.iter().cloned().fold(f64::MAX, f64::min);
+                let flow: f64 = p.iter().sum::<f64>() / p.len() as f64;
+                assert!((min + 1.0).abs() < 0.02, "min {}", min);
+                let closure = model.compute(model.closure_instant() - 1e-6);
+                assert!((closure + 1.0).abs() < 0.02, "closure {}", closure);
+                assert!(flow.abs() < 0.05, "net flow {}", flow);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wavetable_interpolates() {
+        let w = Wavetable::new(vec![0.0, 1.0, 0.0, -1.0]).unwrap();
+        assert_eq!(w.compute(0.0), 0.0);
+        assert_eq!(w.compute(0.125), 0.5);
+        assert_eq!(w.compute(0.875), -0.5);
+        assert_eq!(w.closure_instant(), 0.75);
+        assert_eq!(Wavetable::new(vec![]).err(), Some(WavetableError::Empty));
+    }
+}
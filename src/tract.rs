@@ -1,4 +1,5 @@
-use crate::consts::BASE_N;
+use crate::consts::{BASE_N, MAX_TRANSIENTS};
+use crate::float::Float;
 use crate::transient::TransientPool;
 
 fn move_towards(current: f64, target: f64, amt_up: f64, amt_down: f64) -> f64 {
@@ -9,7 +10,10 @@ fn move_towards(current: f64, target: f64, amt_up: f64, amt_down: f64) -> f64 {
     }
 }
 
-pub struct Tract {
+/// The waveguide. Diameters and other articulation state are `f64`; the
+/// travelling waves and reflection coefficients use the sample type `F`.
+#[derive(Clone)]
+pub struct Tract<F: Float = f64> {
     pub sr: f64,
     pub n: usize,
     pub blade_start: usize,
@@ -20,49 +24,51 @@ pub struct Tract {
     rest_diameter: Vec<f64>,
     pub target_diameter: Vec<f64>,
     new_diameter: Vec<f64>,
-    r: Vec<f64>,
-    l: Vec<f64>,
-    reflection: Vec<f64>,
-    new_reflection: Vec<f64>,
-    junction_outl: Vec<f64>,
-    junction_outr: Vec<f64>,
+    r: Vec<F>,
+    l: Vec<F>,
+    reflection: Vec<F>,
+    new_reflection: Vec<F>,
+    junction_outl: Vec<F>,
+    junction_outr: Vec<F>,
     a: Vec<f64>,
 
     pub nose_length: usize,
     pub nose_start: usize,
     pub tip_start: usize,
-    nosel: Vec<f64>,
-    noser: Vec<f64>,
-    nose_junc_outl: Vec<f64>,
-    nose_junc_outr: Vec<f64>,
-    nose_reflection: Vec<f64>,
+    nosel: Vec<F>,
+    noser: Vec<F>,
+    nose_junc_outl: Vec<F>,
+    nose_junc_outr: Vec<F>,
+    nose_reflection: Vec<F>,
     pub nose_diameter: Vec<f64>,
     nose_a: Vec<f64>,
 
-    reflection_left: f64,
-    reflection_right: f64,
-    reflection_nose: f64,
+    reflection_left: F,
+    reflection_right: F,
+    reflection_nose: F,
 
-    new_reflection_left: f64,
-    new_reflection_right: f64,
-    new_reflection_nose: f64,
+    new_reflection_left: F,
+    new_reflection_right: F,
+    new_reflection_nose: F,
 
     pub velum_target: f64,
+    pub fricative_intensity: f64,
 
-    glottal_reflection: f64,
-    lip_reflection: f64,
+    glottal_reflection: F,
+    lip_reflection: F,
     last_obstruction: i32,
     pub fade: f64,
-    movement_speed: f64,
-    pub lip_output: f64,
-    pub nose_output: f64,
-    block_time: f64,
+    pub movement_speed: f64,
+    pub lip_output: F,
+    pub nose_output: F,
+    pub noise_output: F,
 
     tpool: TransientPool,
     t: f64,
 }
 
-impl Tract {
+impl<F: Float> Tract<F> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         samplerate: f64,
         n: usize,
@@ -83,38 +89,39 @@ impl Tract {
             rest_diameter: vec![0.0; n],
             target_diameter: vec![0.0; n],
             new_diameter: vec![0.0; n],
-            r: vec![0.0; n],
-            l: vec![0.0; n],
-            reflection: vec![0.0; n + 1],
-            new_reflection: vec![0.0; n + 1],
-            junction_outl: vec![0.0; n + 1],
-            junction_outr: vec![0.0; n + 1],
+            r: vec![F::default(); n],
+            l: vec![F::default(); n],
+            reflection: vec![F::default(); n + 1],
+            new_reflection: vec![F::default(); n + 1],
+            junction_outl: vec![F::default(); n + 1],
+            junction_outr: vec![F::default(); n + 1],
             a: vec![0.0; n],
             nose_length,
             nose_start,
             tip_start,
-            nosel: vec![0.0; nose_length],
-            noser: vec![0.0; nose_length],
-            nose_junc_outl: vec![0.0; nose_length + 1],
-            nose_junc_outr: vec![0.0; nose_length + 1],
-            nose_reflection: vec![0.0; nose_length + 1],
+            nosel: vec![F::default(); nose_length],
+            noser: vec![F::default(); nose_length],
+            nose_junc_outl: vec![F::default(); nose_length + 1],
+            nose_junc_outr: vec![F::default(); nose_length + 1],
+            nose_reflection: vec![F::default(); nose_length + 1],
             nose_diameter: vec![0.0; nose_length],
             nose_a: vec![0.0; nose_length],
-            reflection_left: 0.0,
-            reflection_right: 0.0,
-            reflection_nose: 0.0,
-            new_reflection_left: 0.0,
-            new_reflection_right: 0.0,
-            new_reflection_nose: 0.0,
+            reflection_left: F::default(),
+            reflection_right: F::default(),
+            reflection_nose: F::default(),
+            new_reflection_left: F::default(),
+            new_reflection_right: F::default(),
+            new_reflection_nose: F::default(),
             velum_target: 0.01,
-            glottal_reflection: 0.75,
-            lip_reflection: -0.85,
+            fricative_intensity: 1.0,
+            glottal_reflection: F::from_f64(0.75),
+            lip_reflection: F::from_f64(-0.85),
             last_obstruction: -1,
             fade: 0.0,
             movement_speed: 15.0,
-            lip_output: 0.0,
-            nose_output: 0.0,
-            block_time: 512.0 / samplerate,
+            lip_output: F::default(),
+            nose_output: F::default(),
+            noise_output: F::default(),
             tpool: TransientPool::new(),
             t: 1.0 / samplerate,
         };
@@ -160,6 +167,15 @@ impl Tract {
         }
     }
 
+    /// Replaces the nose shape. Segment 0 is the velum opening and keeps
+    /// moving towards `velum_target`.
+    pub fn set_nose_diameter(&mut self, diameters: &[f64]) {
+        for (d, &new) in self.nose_diameter.iter_mut().zip(diameters).skip(1) {
+            *d = new;
+        }
+        self.calculate_nose_reflections();
+    }
+
     pub fn calculate_reflections(&mut self) {
         for i in 0..self.n {
             self.a[i] = self.diameter[i].powi(2);
@@ -167,11 +183,11 @@ impl Tract {
 
         for i in 1..self.n {
             self.reflection[i] = self.new_reflection[i];
-            self.new_reflection[i] = if self.a[i] == 0.0 {
+            self.new_reflection[i] = F::from_f64(if self.a[i] == 0.0 {
                 0.999
             } else {
                 (self.a[i - 1] - self.a[i]) / (self.a[i - 1] + self.a[i])
-            };
+            });
         }
 
         self.reflection_left = self.new_reflection_left;
@@ -179,9 +195,9 @@ impl Tract {
         self.reflection_nose = self.new_reflection_nose;
 
         let sum = self.a[self.nose_start] + self.a[self.nose_start + 1] + self.nose_a[0];
-        self.new_reflection_left = (2.0 * self.a[self.nose_start] - sum) / sum;
-        self.new_reflection_right = (2.0 * self.a[self.nose_start + 1] - sum) / sum;
-        self.new_reflection_nose = (2.0 * self.nose_a[0] - sum) / sum;
+        self.new_reflection_left = F::from_f64((2.0 * self.a[self.nose_start] - sum) / sum);
+        self.new_reflection_right = F::from_f64((2.0 * self.a[self.nose_start + 1] - sum) / sum);
+        self.new_reflection_nose = F::from_f64((2.0 * self.nose_a[0] - sum) / sum);
     }
 
     fn calculate_nose_reflections(&mut self) {
@@ -190,30 +206,33 @@ impl Tract {
         }
 
         for i in 1..self.nose_length {
-            self.nose_reflection[i] =
-                (self.nose_a[i - 1] - self.nose_a[i]) / (self.nose_a[i - 1] + self.nose_a[i]);
+            self.nose_reflection[i] = F::from_f64(
+                (self.nose_a[i - 1] - self.nose_a[i]) / (self.nose_a[i - 1] + self.nose_a[i]),
+            );
         }
     }
 
-    pub fn compute(&mut self, input: f64, lambda: f64) {
-        let mut transients_to_remove: Vec<usize> = Vec::new();
-
-        {
-            let transients = self.tpool.get_valid_transients();
-            for n in transients {
-                let amp = n.strength * 2.0f64.powf(-1.0 * n.exponent * n.time_alive);
-                self.l[n.position] += amp * 0.5;
-                self.r[n.position] += amp * 0.5;
-                n.time_alive += self.t * 0.5;
-                if n.time_alive > n.lifetime {
-                    transients_to_remove.push(n.id)
+    pub fn compute(&mut self, input: F, turbulence: F, lambda: F) {
+        self.noise_output = F::default();
+        for id in 0..MAX_TRANSIENTS {
+            let expired = match self.tpool.get_mut(id) {
+                Some(n) => {
+                    let amp = n.strength * 2.0f64.powf(-n.exponent * n.time_alive);
+                    let half = F::from_f64(amp * 0.5);
+                    self.l[n.position] += half;
+                    self.r[n.position] += half;
+                    self.noise_output += F::from_f64(amp);
+                    n.time_alive += self.t * 0.5;
+                    n.time_alive > n.lifetime
                 }
+                None => false,
+            };
+            if expired {
+                self.tpool.remove(id);
             }
         }
 
-        for id in transients_to_remove {
-            self.tpool.remove(id);
-        }
+        self.add_turbulence_noise(turbulence);
 
         self.junction_outr[0] = self.l[0] * self.glottal_reflection + input;
         self.junction_outl[self.n] = self.r[self.n - 1] * self.lip_reflection;
@@ -221,12 +240,13 @@ impl Tract {
         self.calculate_junctions(lambda);
 
         let i = self.nose_start;
-        let r = self.new_reflection_left * (1.0 - lambda) + self.reflection_left * lambda;
-        self.junction_outl[i] = r * self.r[i - 1] + (1.0 + r) * (self.nosel[0] + self.l[i]);
-        let r = self.new_reflection_right * (1.0 - lambda) + self.reflection_right * lambda;
-        self.junction_outr[i] = r * self.l[i] + (1.0 + r) * (self.r[i - 1] + self.nosel[0]);
-        let r = self.new_reflection_nose * (1.0 - lambda) + self.reflection_nose * lambda;
-        self.nose_junc_outr[0] = r * self.nosel[0] + (1.0 + r) * (self.l[i] + self.r[i - 1]);
+        let one = F::from_f64(1.0);
+        let r = self.new_reflection_left * (one - lambda) + self.reflection_left * lambda;
+        self.junction_outl[i] = r * self.r[i - 1] + (one + r) * (self.nosel[0] + self.l[i]);
+        let r = self.new_reflection_right * (one - lambda) + self.reflection_right * lambda;
+        self.junction_outr[i] = r * self.l[i] + (one + r) * (self.r[i - 1] + self.nosel[0]);
+        let r = self.new_reflection_nose * (one - lambda) + self.reflection_nose * lambda;
+        self.nose_junc_outr[0] = r * self.nosel[0] + (one + r) * (self.l[i] + self.r[i - 1]);
 
         self.calculate_lip_output();
 
@@ -239,6 +259,41 @@ impl Tract {
         self.nose_output = self.noser[self.nose_length - 1];
     }
 
+    // Injects frication noise just past the tightest point of every narrow
+    // region above the trachea. Fully closed regions stay silent.
+    fn add_turbulence_noise(&mut self, turbulence: F) {
+        let noise = turbulence * F::from_f64(0.66 * self.fricative_intensity);
+        if noise == F::default() {
+            return;
+        }
+
+        let mut i = self.epiglottis_start;
+        while i < self.n {
+            if self.diameter[i] >= 0.7 {
+                i += 1;
+                continue;
+            }
+
+            let mut tightest = i;
+            while i < self.n && self.diameter[i] < 0.7 {
+                if self.diameter[i] < self.diameter[tightest] {
+                    tightest = i;
+                }
+                i += 1;
+            }
+
+            let diameter = self.diameter[tightest];
+            let thinness = (8.0 * (0.7 - diameter)).clamp(0.0, 1.0);
+            let openness = (30.0 * (diameter - 0.3)).clamp(0.0, 1.0);
+            let amp = noise * F::from_f64(thinness * openness * 0.5);
+
+            let pos = (tightest + 1).min(self.n - 1);
+            self.r[pos] += amp;
+            self.l[pos] += amp;
+            self.noise_output += amp + amp;
+        }
+    }
+
     fn calculate_nose(&mut self) {
         self.noser[..self.nose_length].copy_from_slice(&self.nose_junc_outr[..self.nose_length]);
         self.nosel[..self.nose_length].copy_from_slice(&self.nose_junc_outl[1..=self.nose_length]);
@@ -253,25 +308,29 @@ impl Tract {
     }
 
     fn calculate_lip_output(&mut self) {
+        let damping = F::from_f64(0.999);
         for i in 0..self.n {
-            self.r[i] = self.junction_outr[i] * 0.999;
-            self.l[i] = self.junction_outl[i + 1] * 0.999;
+            self.r[i] = self.junction_outr[i] * damping;
+            self.l[i] = self.junction_outl[i + 1] * damping;
         }
         self.lip_output = self.r[self.n - 1];
     }
 
-    fn calculate_junctions(&mut self, lambda: f64) {
+    fn calculate_junctions(&mut self, lambda: F) {
+        let one = F::from_f64(1.0);
         for i in 1..self.n {
-            let r = self.reflection[i] * (1.0 - lambda) + self.new_reflection[i] * lambda;
+            let r = self.reflection[i] * (one - lambda) + self.new_reflection[i] * lambda;
             let w = r * (self.r[i - 1] + self.l[i]);
             self.junction_outr[i] = self.r[i - 1] - w;
             self.junction_outl[i] = self.l[i] + w;
         }
     }
 
-    pub fn reshape(&mut self) {
+    /// Moves the diameters and the velum towards their targets over
+    /// `block_time` seconds.
+    pub fn reshape(&mut self, block_time: f64) {
         let mut current_obstruction = -1;
-        let amount = self.block_time * self.movement_speed;
+        let amount = block_time * self.movement_speed;
 
         for i in 0..self.n {
             let slow_return = if i < self.nose_start {
@@ -363,3 +422,39 @@ impl Tract {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tract() -> Tract {
+        Tract::new(44100.0, 44, 28, 17, 32, 12, 6, 39)
+    }
+
+    fn energy(tract: &mut Tract, turbulence: f64) -> f64 {
+        let mut sum = 0.0;
+        for i in 0..1000 {
+            let noise = if i % 2 == 0 { turbulence } else { -turbulence };
+            tract.compute(0.0, noise, 0.0);
+            sum += tract.lip_output.powi(2);
+        }
+        sum
+    }
+
+    #[test]
+    fn test_turbulence_at_constriction() {
+        let mut t = tract();
+        t.diameter[30] = 0.4;
+        assert!(energy(&mut t, 1.0) > 0.0);
+    }
+
+    #[test]
+    fn test_no_turbulence_when_open_or_closed() {
+        let mut t = tract();
+        assert_eq!(energy(&mut t, 1.0), 0.0);
+
+        let mut t = tract();
+        t.diameter[30] = 0.0;
+        assert_eq!(energy(&mut t, 1.0), 0.0);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::consts::MAX_TRANSIENTS;
 
+#[derive(Clone)]
 pub struct Transient {
     pub position: usize,
     pub time_alive: f64,
@@ -23,6 +24,7 @@ impl Transient {
         }
     }
 }
+#[derive(Clone)]
 pub struct TransientPool {
     pool: Vec<Transient>,
     free_ids: Vec<usize>,
@@ -32,16 +34,16 @@ impl TransientPool {
     pub fn new() -> Self {
         let mut pool = Vec::with_capacity(MAX_TRANSIENTS);
         let mut free_ids = Vec::with_capacity(MAX_TRANSIENTS);
-        for i in (0..MAX_TRANSIENTS).rev() {
+        for i in 0..MAX_TRANSIENTS {
             pool.push(Transient::new(i));
-            free_ids.push(i);
+            free_ids.push(MAX_TRANSIENTS - 1 - i);
         }
         TransientPool { pool, free_ids }
     }
 
     pub fn append(&mut self, position: usize) {
         if let Some(free_id) = self.free_ids.pop() {
-            let t = &mut self.pool[free_id as usize];
+            let t = &mut self.pool[free_id];
             t.is_free = false;
             t.time_alive = 0.0;
 
@@ -53,8 +55,8 @@ impl TransientPool {
     }
 
     pub fn remove(&mut self, id: usize) {
-        if !self.pool[id as usize].is_free {
-            self.pool[id as usize].is_free = true;
+        if !self.pool[id].is_free {
+            self.pool[id].is_free = true;
             self.free_ids.push(id);
         }
     }
@@ -63,11 +65,21 @@ impl TransientPool {
         MAX_TRANSIENTS - self.free_ids.len()
     }
 
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Transient> {
+        self.pool.get_mut(id).filter(|t| !t.is_free)
+    }
+
     pub fn get_valid_transients(&mut self) -> Vec<&mut Transient> {
         self.pool.iter_mut().filter(|t| !t.is_free).collect()
     }
 }
 
+impl Default for TransientPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -107,6 +119,17 @@ mod tests {
         assert_eq!(pool.size(), 0);
     }
 
+    #[test]
+    fn test_transient_pool_remove_by_id() {
+        let mut pool = TransientPool::new();
+        pool.append(5);
+        pool.append(6);
+        let id = pool.get_valid_transients()[1].id;
+        pool.remove(id);
+        assert_eq!(pool.size(), 1);
+        assert!(pool.get_mut(id).is_none());
+    }
+
     #[test]
     fn test_transient_pool_max_capacity() {
         let mut pool = TransientPool::new();
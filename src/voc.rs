@@ -1,20 +1,87 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::f64::consts::PI;
 use std::ops::Range;
 
-use crate::glottis::Glottis;
+use crate::automation::{Automation, Param};
+use crate::config::{ConfigError, VocConfig};
+use crate::consts::BASE_N;
+use crate::float::Float;
+use crate::glottis::{Glottis, PitchMark, PitchMarkKind};
+use crate::noise::BandpassFilter;
+use crate::phoneme::ArticulatoryTarget;
+#[cfg(feature = "preset")]
+use crate::preset::{Geometry, PresetError, VocState};
+use crate::source::GlottalSource;
 use crate::tract::Tract;
 
-pub struct Voc {
+/// One sample of every output stream. `lips` and `nose` are the radiated
+/// oral and nasal sound and sum to the mono output. `glottis` is the source
+/// signal entering the tract and `noise` the frication and transient energy
+/// injected into it. All four carry the output scaler.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Frame<F: Float = f64> {
+    pub lips: F,
+    pub nose: F,
+    pub glottis: F,
+    pub noise: F,
+}
+
+impl<F: Float> Frame<F> {
+    pub fn mono(&self) -> F {
+        self.lips + self.nose
+    }
+}
+
+/// The complete simulation state of a `Voc`, taken with `Voc::snapshot`.
+/// Restoring it continues the render sample for sample where the snapshot
+/// was taken, which lets several renders branch from one shared prefix.
+#[derive(Clone)]
+pub struct VocSnapshot<F: Float = f64> {
+    voc: Voc<F>,
+}
+
+impl<F: Float> VocSnapshot<F> {
+    /// Samples the voice had rendered when the snapshot was taken.
+    pub fn sample_time(&self) -> u64 {
+        self.voc.sample_time
+    }
+}
+
+/// A complete voice. `F` is the sample type of the waveguide and of the
+/// rendered output; `Voc<f32>` trades some precision for speed.
+///
+/// The glottis, its pulse sources and the noise filters run in `f64` for
+/// either type. They take a handful of operations per sample next to the
+/// tract's per-segment loop, and sharing them keeps pitch and pitch marks
+/// identical between `Voc<f32>` and `Voc<f64>`.
+#[derive(Clone)]
+pub struct Voc<F: Float = f64> {
     glottis: Glottis,
-    tract: Tract,
-    buf: Vec<f64>,
+    tract: Tract<F>,
+    fricative_filter: BandpassFilter,
+    rng: StdRng,
+    buf: Vec<F>,
+    marks: Vec<PitchMark>,
+    max_pitch_marks: usize,
+    dropped_pitch_marks: usize,
     pub sr: f64,
     chunk: usize,
     vocal_output_scaler: f64,
     pub counter: usize,
+    reshape_interval: usize,
+    block_pos: usize,
+    sample_time: u64,
+    automation: Automation,
+    tongue_index: f64,
+    tongue_diameter: f64,
+    lips: f64,
+    config: VocConfig,
 }
 
 impl Voc {
+    /// Positional form of `from_config`, validated the same way.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         samplerate: f64,
         chunk: usize,
@@ -28,10 +95,13 @@ impl Voc {
         blade_start: usize,
         epiglottis_start: usize,
         lip_start: usize,
-    ) -> Self {
-        let glottis = Glottis::new(samplerate, default_freq, default_tenseness);
-        let tract = Tract::new(
+    ) -> Result<Self, ConfigError> {
+        Self::from_config(VocConfig {
             samplerate,
+            chunk,
+            vocal_output_scaler,
+            frequency: default_freq,
+            tenseness: default_tenseness,
             n,
             nose_length,
             nose_start,
@@ -39,20 +109,93 @@ impl Voc {
             blade_start,
             epiglottis_start,
             lip_start,
+            seed: None,
+        })
+    }
+
+    /// Builds a voice from a configuration, rejecting geometry the tract
+    /// cannot simulate.
+    pub fn from_config(config: VocConfig) -> Result<Self, ConfigError> {
+        Self::with_config(config)
+    }
+}
+
+impl<F: Float> Voc<F> {
+    /// Like `from_config`, for any sample type, e.g.
+    /// `Voc::<f32>::with_config(config)`.
+    pub fn with_config(config: VocConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+        Ok(Self::build(config))
+    }
+
+    fn build(config: VocConfig) -> Self {
+        let glottis = Glottis::new(config.samplerate, config.frequency, config.tenseness);
+        let tract = Tract::new(
+            config.samplerate,
+            config.n,
+            config.nose_length,
+            config.nose_start,
+            config.tip_start,
+            config.blade_start,
+            config.epiglottis_start,
+            config.lip_start,
         );
-        let buf = vec![0.0; chunk];
+        let lips = tract.lips();
+        let buf = vec![F::default(); config.chunk];
+
+        let fricative_filter = BandpassFilter::new(config.samplerate, 1000.0, 0.5);
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
 
         Voc {
             glottis,
             tract,
+            fricative_filter,
+            rng,
             buf,
-            sr: samplerate,
-            chunk,
-            vocal_output_scaler,
+            marks: Vec::with_capacity(config.chunk),
+            max_pitch_marks: config.chunk,
+            dropped_pitch_marks: 0,
+            sr: config.samplerate,
+            chunk: config.chunk,
+            vocal_output_scaler: config.vocal_output_scaler,
             counter: 0,
+            reshape_interval: config.chunk,
+            block_pos: 0,
+            sample_time: 0,
+            automation: Automation::new(),
+            tongue_index: 12.9,
+            tongue_diameter: 2.43,
+            lips,
+            config,
         }
     }
 
+    pub fn config(&self) -> &VocConfig {
+        &self.config
+    }
+
+    /// Reseeds the generator behind every noise source. Two voices seeded
+    /// alike before rendering produce identical output for identical input.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Captures everything the next samples depend on: the waveguide and
+    /// nose state, glottal phase, active transients, noise generator,
+    /// automation and block position, along with the configuration.
+    pub fn snapshot(&self) -> VocSnapshot<F> {
+        VocSnapshot { voc: self.clone() }
+    }
+
+    /// Replaces this voice's whole state, configuration included, with
+    /// `snapshot`. The voice may have come from any configuration.
+    pub fn restore(&mut self, snapshot: &VocSnapshot<F>) {
+        self.clone_from(&snapshot.voc);
+    }
+
     pub fn frequency(&self) -> f64 {
         self.glottis.freq
     }
@@ -88,6 +231,17 @@ impl Voc {
         self.glottis.tenseness = t;
     }
 
+    pub fn lips(&self) -> f64 {
+        self.lips
+    }
+
+    /// Sets the opening of the lip segments. Constrictions placed over the
+    /// lips by `apply_target` release back to this opening.
+    pub fn set_lips(&mut self, d: f64) {
+        self.lips = d;
+        self.tract.set_lips(d);
+    }
+
     pub fn velum(&self) -> f64 {
         self.tract.velum_target
     }
@@ -96,30 +250,359 @@ impl Voc {
         self.tract.velum_target = t;
     }
 
-    pub fn step(&mut self) -> &[f64] {
-        self.tract.reshape();
-        self.tract.calculate_reflections();
+    /// Swaps the glottal pulse model, e.g. for `Rosenberg` or a `Wavetable`.
+    pub fn set_glottal_source(&mut self, source: Box<dyn GlottalSource>) {
+        self.glottis.set_source(source);
+    }
+
+    pub fn intensity(&self) -> f64 {
+        self.glottis.intensity
+    }
 
-        for i in 0..self.chunk {
-            let mut vocal_output = 0.0;
-            let lambda1 = i as f64 / self.chunk as f64;
-            let lambda2 = (i as f64 + 0.5) / self.chunk as f64;
+    /// Sets the loudness of the glottal source, from 0 (silent) to 1.
+    pub fn set_intensity(&mut self, i: f64) {
+        self.glottis.intensity = i;
+    }
+
+    pub fn is_voiced(&self) -> bool {
+        self.glottis.voiced
+    }
+
+    /// Fades the voice in over the attack time.
+    pub fn voice_on(&mut self) {
+        self.glottis.voiced = true;
+    }
+
+    /// Fades the voice out over the release time. Aspiration and frication
+    /// keep flowing only while breathing.
+    pub fn voice_off(&mut self) {
+        self.glottis.voiced = false;
+    }
+
+    // Like `voice_off` without the release, for voices that should start
+    // out silent.
+    pub(crate) fn silence(&mut self) {
+        self.glottis.silence();
+    }
+
+    pub fn is_breathing(&self) -> bool {
+        self.glottis.breathing
+    }
+
+    /// Keeps air flowing through the glottis while the voice is off, so
+    /// aspiration sounds as it does for /h/. Fades over the attack and
+    /// release times.
+    pub fn set_breathing(&mut self, breathing: bool) {
+        self.glottis.breathing = breathing;
+    }
+
+    pub fn voice_attack(&self) -> f64 {
+        self.glottis.attack
+    }
+
+    pub fn set_voice_attack(&mut self, seconds: f64) {
+        self.glottis.attack = seconds;
+    }
+
+    pub fn voice_release(&self) -> f64 {
+        self.glottis.release
+    }
+
+    pub fn set_voice_release(&mut self, seconds: f64) {
+        self.glottis.release = seconds;
+    }
+
+    pub fn vibrato_rate(&self) -> f64 {
+        self.glottis.vibrato_frequency
+    }
+
+    pub fn set_vibrato_rate(&mut self, hz: f64) {
+        self.glottis.vibrato_frequency = hz;
+    }
 
-            let glot = self.glottis.compute(lambda1);
+    pub fn vibrato_depth(&self) -> f64 {
+        self.glottis.vibrato_amount
+    }
 
-            self.tract.compute(glot, lambda1);
-            vocal_output += self.tract.lip_output + self.tract.nose_output;
+    /// Sets the vibrato depth as a fraction of the frequency (0.01 = 1%).
+    pub fn set_vibrato_depth(&mut self, depth: f64) {
+        self.glottis.vibrato_amount = depth;
+    }
 
-            self.tract.compute(glot, lambda2);
-            vocal_output += self.tract.lip_output + self.tract.nose_output;
+    pub fn pitch_wobble(&self) -> f64 {
+        self.glottis.wobble
+    }
+
+    /// Scales the slow random pitch drift. 0 disables it.
+    pub fn set_pitch_wobble(&mut self, w: f64) {
+        self.glottis.wobble = w;
+    }
+
+    pub fn jitter(&self) -> f64 {
+        self.glottis.jitter
+    }
+
+    /// Sets the random cycle-to-cycle period perturbation, relative to the
+    /// period (0.01 = up to 1%).
+    pub fn set_jitter(&mut self, j: f64) {
+        self.glottis.jitter = j;
+    }
+
+    pub fn shimmer(&self) -> f64 {
+        self.glottis.shimmer
+    }
+
+    /// Sets the random cycle-to-cycle amplitude perturbation, relative to the
+    /// amplitude (0.05 = up to 5%).
+    pub fn set_shimmer(&mut self, s: f64) {
+        self.glottis.shimmer = s;
+    }
+
+    /// Sets the center frequency (Hz) and Q of the bandpass that shapes the
+    /// aspiration noise. Defaults to 500 Hz, Q 0.5.
+    pub fn set_aspiration_filter(&mut self, center: f64, q: f64) {
+        self.glottis.set_aspiration_filter(center, q);
+    }
+
+    pub fn movement_speed(&self) -> f64 {
+        self.tract.movement_speed
+    }
+
+    /// Sets how fast the tract diameters follow their targets, in diameter
+    /// units per second.
+    pub fn set_movement_speed(&mut self, speed: f64) {
+        self.tract.movement_speed = speed;
+    }
+
+    pub fn fricative_intensity(&self) -> f64 {
+        self.tract.fricative_intensity
+    }
+
+    pub fn set_fricative_intensity(&mut self, i: f64) {
+        self.tract.fricative_intensity = i;
+    }
+
+    /// Period starts and glottal closure instants of the last block rendered
+    /// by `step` or `process`, indexed into that block. They mark the source;
+    /// the radiated output lags them by the propagation delay of the tract.
+    pub fn pitch_marks(&self) -> &[PitchMark] {
+        &self.marks
+    }
+
+    pub fn max_pitch_marks(&self) -> usize {
+        self.max_pitch_marks
+    }
+
+    /// How many pitch marks the last render call dropped past
+    /// `max_pitch_marks`.
+    pub fn dropped_pitch_marks(&self) -> usize {
+        self.dropped_pitch_marks
+    }
+
+    /// Sets how many pitch marks one render call keeps, by default the chunk
+    /// size. Later marks in the call are dropped so rendering never
+    /// allocates, and counted by `dropped_pitch_marks`; raise this before
+    /// rendering blocks much longer than a chunk. Allocates, so call it
+    /// outside the audio thread.
+    pub fn set_max_pitch_marks(&mut self, marks: usize) {
+        self.marks.reserve(marks.saturating_sub(self.marks.len()));
+        self.max_pitch_marks = marks;
+    }
+
+    pub fn reshape_interval(&self) -> usize {
+        self.reshape_interval
+    }
 
-            self.buf[i] = vocal_output * self.vocal_output_scaler;
+    /// Sets how many samples pass between articulator updates. Defaults to
+    /// the chunk size; smaller values let diameters approach their targets
+    /// in finer steps, down to every sample at 1, for O(n) extra work per
+    /// update. Takes effect at the next sample.
+    pub fn set_reshape_interval(&mut self, samples: usize) {
+        self.reshape_interval = samples.max(1);
+        self.block_pos = 0;
+    }
+
+    pub fn step(&mut self) -> &[F] {
+        self.clear_marks();
+        for i in 0..self.chunk {
+            self.buf[i] = self.tick(i).mono();
         }
 
         &self.buf
     }
 
-    pub fn compute(&mut self) -> f64 {
+    /// Renders `out.len()` samples into `out`. Any length works, and the
+    /// articulators keep their own update grid across calls, so hosts can use
+    /// varying block sizes. Pitch marks are indexed into `out`; at most
+    /// `max_pitch_marks` are kept per call and the rest are counted by
+    /// `dropped_pitch_marks`.
+    pub fn process(&mut self, out: &mut [F]) {
+        self.clear_marks();
+        for (i, sample) in out.iter_mut().enumerate() {
+            *sample = self.tick(i).mono();
+        }
+    }
+
+    /// Like `process`, keeping the output streams apart.
+    pub fn process_frames(&mut self, out: &mut [Frame<F>]) {
+        self.clear_marks();
+        for (i, frame) in out.iter_mut().enumerate() {
+            *frame = self.tick(i);
+        }
+    }
+
+    fn mark(&mut self, index: usize, kind: PitchMarkKind) {
+        if self.marks.len() < self.max_pitch_marks {
+            self.marks.push(PitchMark { index, kind });
+        } else {
+            self.dropped_pitch_marks += 1;
+        }
+    }
+
+    fn clear_marks(&mut self) {
+        self.marks.clear();
+        self.dropped_pitch_marks = 0;
+    }
+
+    /// Samples rendered since the voice was created. Automation breakpoints
+    /// are placed on this clock.
+    pub fn sample_time(&self) -> u64 {
+        self.sample_time
+    }
+
+    pub fn automation(&self) -> &Automation {
+        &self.automation
+    }
+
+    pub fn automation_mut(&mut self) -> &mut Automation {
+        &mut self.automation
+    }
+
+    pub fn set_automation(&mut self, automation: Automation) {
+        self.automation = automation;
+    }
+
+    // Writes the automated values for the current sample. A lane only writes
+    // when its value changes, so setters hold once the lane has settled.
+    // Glottal lanes skip the per-block glide in `Glottis` and take effect on
+    // their exact sample.
+    fn apply_automation(&mut self) {
+        let time = self.sample_time;
+        let mut automation = std::mem::take(&mut self.automation);
+
+        let mut tongue_changed = false;
+        for lane in automation.lanes_mut() {
+            if !matches!(lane.param, Param::TongueIndex | Param::TongueDiameter) {
+                continue;
+            }
+            let Some(value) = lane.value_at(time) else {
+                continue;
+            };
+            if lane.last != Some(value) {
+                lane.last = Some(value);
+                tongue_changed = true;
+                if lane.param == Param::TongueIndex {
+                    self.tongue_index = value;
+                } else {
+                    self.tongue_diameter = value;
+                }
+            }
+        }
+        if tongue_changed {
+            self.tongue_shape(self.tongue_index, self.tongue_diameter);
+        }
+
+        for lane in automation.lanes_mut() {
+            let Some(value) = lane.value_at(time) else {
+                continue;
+            };
+            // A new tongue shape overwrites the lips and any single segments.
+            let reshaped = tongue_changed && matches!(lane.param, Param::Lips | Param::Diameter(_));
+            if lane.last == Some(value) && !reshaped {
+                continue;
+            }
+            lane.last = Some(value);
+            match lane.param {
+                Param::Frequency => self.glottis.jump_freq(value),
+                Param::Tenseness => self.glottis.jump_tenseness(value),
+                Param::Intensity => self.glottis.jump_intensity(value),
+                Param::Velum => self.set_velum(value),
+                Param::Lips => self.set_lips(value),
+                Param::Epiglottis => self.tract.set_epiglottis(value),
+                Param::Trachea => self.tract.set_trachea(value),
+                Param::VibratoDepth => self.glottis.vibrato_amount = value,
+                Param::VibratoRate => self.glottis.vibrato_frequency = value,
+                Param::Voicing => {
+                    if value > 0.5 {
+                        self.voice_on();
+                    } else {
+                        self.voice_off();
+                    }
+                }
+                Param::Breathing => self.set_breathing(value > 0.5),
+                Param::FricativeIntensity => self.set_fricative_intensity(value),
+                Param::Diameter(i) => {
+                    if let Some(d) = self.tract.target_diameter.get_mut(i) {
+                        *d = value;
+                    }
+                }
+                Param::TongueIndex | Param::TongueDiameter => {}
+            }
+        }
+
+        self.automation = automation;
+    }
+
+    // Renders one sample, updating the articulators at the start of every
+    // reshape interval. `index` positions pitch marks within the current
+    // render call.
+    fn tick(&mut self, index: usize) -> Frame<F> {
+        let len = self.reshape_interval;
+        if !self.automation.is_empty() {
+            self.apply_automation();
+        }
+        if self.block_pos == 0 {
+            self.glottis.begin_block();
+            self.tract.reshape(len as f64 / self.sr);
+            self.tract.calculate_reflections();
+        }
+
+        let mut frame = Frame::<F>::default();
+        let lambda1 = self.block_pos as f64 / len as f64;
+        let lambda2 = (self.block_pos as f64 + 0.5) / len as f64;
+
+        let glot = self.glottis.compute(&mut self.rng, lambda1);
+        if self.glottis.period_started() {
+            self.mark(index, PitchMarkKind::PeriodStart);
+        }
+        if self.glottis.closed() {
+            self.mark(index, PitchMarkKind::GlottalClosure);
+        }
+        let noise = self.fricative_filter.process(self.rng.gen_range(-1.0..1.0));
+        let turbulence = noise * self.glottis.noise_modulator();
+
+        let (glot, turbulence) = (F::from_f64(glot), F::from_f64(turbulence));
+        for lambda in [lambda1, lambda2] {
+            self.tract.compute(glot, turbulence, F::from_f64(lambda));
+            frame.lips += self.tract.lip_output;
+            frame.nose += self.tract.nose_output;
+            frame.noise += self.tract.noise_output;
+        }
+        frame.glottis = glot;
+
+        self.block_pos = (self.block_pos + 1) % len;
+        self.sample_time += 1;
+
+        let scaler = F::from_f64(self.vocal_output_scaler);
+        Frame {
+            lips: frame.lips * scaler,
+            nose: frame.nose * scaler,
+            glottis: frame.glottis * scaler,
+            noise: frame.noise * scaler,
+        }
+    }
+
+    pub fn compute(&mut self) -> F {
         if self.counter == 0 {
             self.step();
         }
@@ -136,23 +619,27 @@ impl Voc {
         tongue_index: f64,
         tongue_diameter: f64,
     ) {
-        for i in blade_start..lip_start {
-            let t = 1.1 * PI * (tongue_index - i as f64) / (tip_start - blade_start) as f64;
-            let fixed_tongue_diameter = 2.0 + (tongue_diameter - 2.0) / 1.5;
-            let mut curve = (1.5 - fixed_tongue_diameter) * t.cos();
+        shape_tongue(
+            &mut self.tract.target_diameter,
+            blade_start,
+            lip_start,
+            tip_start,
+            tongue_index,
+            tongue_diameter,
+        );
+    }
 
-            if i == blade_start.saturating_sub(2) || i == lip_start.saturating_sub(1) {
-                curve *= 0.8;
-            }
-            if i == blade_start || i == lip_start.saturating_sub(2) {
-                curve *= 0.94;
-            }
+    pub fn tongue_index(&self) -> f64 {
+        self.tongue_index
+    }
 
-            self.tract.target_diameter[i] = 1.5 - curve;
-        }
+    pub fn tongue_diameter(&self) -> f64 {
+        self.tongue_diameter
     }
 
     pub fn tongue_shape(&mut self, tongue_index: f64, tongue_diameter: f64) {
+        self.tongue_index = tongue_index;
+        self.tongue_diameter = tongue_diameter;
         self.set_diameters(
             self.tract.blade_start,
             self.tract.lip_start,
@@ -175,7 +662,15 @@ impl Voc {
         self.tract.set_epiglottis(epiglottis);
         self.set_velum(velum);
         self.tongue_shape(tongue_index, tongue_diameter);
-        self.tract.set_lips(lips);
+        self.set_lips(lips);
+    }
+
+    /// Sets the target diameter of segment `index`, ignoring indices past
+    /// the lips.
+    pub fn set_tract_diameter(&mut self, index: usize, diameter: f64) {
+        if let Some(d) = self.tract.target_diameter.get_mut(index) {
+            *d = diameter;
+        }
     }
 
     pub fn set_tract_diameters(&mut self, range: Range<usize>, diameters: Vec<f64>) {
@@ -186,11 +681,187 @@ impl Voc {
         }
     }
 
-    pub fn play_chunk(&mut self) -> &[f64] {
+    /// The controllable parameters, for saving as a preset.
+    #[cfg(feature = "preset")]
+    pub fn state(&self) -> VocState {
+        VocState {
+            frequency: self.frequency(),
+            tenseness: self.tenseness(),
+            velum: self.velum(),
+            tongue_index: self.tongue_index,
+            tongue_diameter: self.tongue_diameter,
+            lips: self.lips,
+            target_diameter: self.tract.target_diameter.clone(),
+            nose_diameter: self.tract.nose_diameter.clone(),
+            geometry: Geometry::of(&self.config),
+        }
+    }
+
+    /// Restores a preset taken with `state`. The tract moves towards the new
+    /// targets at the usual speed, and the velum opening towards the preset's
+    /// velum. Fails, leaving the voice untouched, if the preset was made for
+    /// another geometry.
+    #[cfg(feature = "preset")]
+    pub fn apply_preset(&mut self, preset: &VocState) -> Result<(), PresetError> {
+        let geometry = Geometry::of(&self.config);
+        if preset.geometry != geometry
+            || preset.target_diameter.len() != self.tract.n
+            || preset.nose_diameter.len() != self.tract.nose_length
+        {
+            return Err(PresetError::GeometryMismatch {
+                expected: geometry,
+                found: preset.geometry,
+            });
+        }
+        self.set_frequency(preset.frequency);
+        self.set_tenseness(preset.tenseness);
+        self.set_velum(preset.velum);
+        self.tongue_index = preset.tongue_index;
+        self.tongue_diameter = preset.tongue_diameter;
+        self.lips = preset.lips;
+        self.tract
+            .target_diameter
+            .copy_from_slice(&preset.target_diameter);
+        self.tract.set_nose_diameter(&preset.nose_diameter);
+        Ok(())
+    }
+
+    /// Narrows the tract around `index` to `diameter`, like a finger pressed on
+    /// the Pink Trombone tract. Only ever lowers the current targets, so call
+    /// it after shaping the tongue.
+    pub fn constrict(&mut self, index: f64, diameter: f64) {
+        constrict(
+            &mut self.tract.target_diameter,
+            self.tract.tip_start,
+            index,
+            diameter,
+        );
+    }
+
+    /// Moves the articulators to `target`. The tongue and lips are reshaped
+    /// first, from the target or else from their last settings, which
+    /// releases the previous target's constrictions. Indices in the target
+    /// refer to the default 44-segment tract and are rescaled to this voice.
+    pub fn apply_target(&mut self, target: &ArticulatoryTarget) {
+        let mut tongue = (self.tongue_index, self.tongue_diameter);
+        shape_target(
+            &self.config,
+            &mut self.tract.target_diameter,
+            target,
+            &mut tongue,
+            &mut self.lips,
+        );
+        (self.tongue_index, self.tongue_diameter) = tongue;
+        if let Some(velum) = target.velum {
+            self.set_velum(velum);
+        }
+        if let Some(tenseness) = target.tenseness {
+            self.set_tenseness(tenseness);
+        }
+        if target.voiced {
+            self.voice_on();
+        } else {
+            self.voice_off();
+        }
+        self.set_breathing(target.breathing);
+        self.set_fricative_intensity(target.frication);
+    }
+
+    pub fn play_chunk(&mut self) -> &[F] {
         self.step()
     }
 }
 
+fn shape_tongue(
+    diameters: &mut [f64],
+    blade_start: usize,
+    lip_start: usize,
+    tip_start: usize,
+    tongue_index: f64,
+    tongue_diameter: f64,
+) {
+    for (i, d) in diameters
+        .iter_mut()
+        .enumerate()
+        .take(lip_start)
+        .skip(blade_start)
+    {
+        let t = 1.1 * PI * (tongue_index - i as f64) / (tip_start - blade_start) as f64;
+        let fixed_tongue_diameter = 2.0 + (tongue_diameter - 2.0) / 1.5;
+        let mut curve = (1.5 - fixed_tongue_diameter) * t.cos();
+
+        if i == blade_start.saturating_sub(2) || i == lip_start.saturating_sub(1) {
+            curve *= 0.8;
+        }
+        if i == blade_start || i == lip_start.saturating_sub(2) {
+            curve *= 0.94;
+        }
+
+        *d = 1.5 - curve;
+    }
+}
+
+fn constrict(diameters: &mut [f64], tip_start: usize, index: f64, diameter: f64) {
+    let tip_start = tip_start as f64;
+    let width = if index < 25.0 {
+        10.0
+    } else if index >= tip_start {
+        5.0
+    } else {
+        10.0 - 5.0 * (index - 25.0) / (tip_start - 25.0)
+    };
+    if width <= 0.0 {
+        return;
+    }
+
+    let diameter = diameter.max(0.0);
+    for (i, target) in diameters.iter_mut().enumerate() {
+        let relpos = i as f64 - index;
+        let relpos = relpos.abs() - 0.5;
+        let shrink = if relpos <= 0.0 {
+            0.0
+        } else if relpos > width {
+            1.0
+        } else {
+            0.5 * (1.0 - (PI * relpos / width).cos())
+        };
+        let d = diameter + (*target - diameter) * shrink;
+        if d < *target {
+            *target = d;
+        }
+    }
+}
+
+// Shapes the target `diameters` of a tract built from `config` for
+// `target`: the tongue and lips, falling back to and updating `tongue` and
+// `lips`, then the constrictions. Lets `Sequence` resolve targets from the
+// geometry alone.
+pub(crate) fn shape_target(
+    config: &VocConfig,
+    diameters: &mut [f64],
+    target: &ArticulatoryTarget,
+    tongue: &mut (f64, f64),
+    lips: &mut f64,
+) {
+    let scale = config.n as f64 / BASE_N as f64;
+    if let Some((index, diameter)) = target.tongue {
+        *tongue = (index * scale, diameter);
+    }
+    shape_tongue(
+        diameters,
+        config.blade_start,
+        config.lip_start,
+        config.tip_start,
+        tongue.0,
+        tongue.1,
+    );
+    *lips = target.lips.unwrap_or(*lips);
+    diameters[config.lip_start..].fill(*lips);
+    for c in &target.constrictions {
+        constrict(diameters, config.tip_start, c.index * scale, c.diameter);
+    }
+}
+
 pub enum Mode {
     None,
     Tongue,
@@ -207,10 +878,11 @@ pub struct VocDemoD {
 }
 
 impl VocDemoD {
-    pub fn new(sr: f64, chunk: usize) -> Self {
-        let voc = Voc::new(sr, chunk, 0.125, 400.0, 0.6, 44, 28, 17, 32, 12, 6, 39);
+    pub fn new(sr: f64, chunk: usize) -> Result<Self, ConfigError> {
+        let config = VocConfig::builder().samplerate(sr).chunk(chunk).build()?;
+        let voc = Voc::from_config(config)?;
 
-        VocDemoD {
+        Ok(VocDemoD {
             sr,
             chunk,
             voc,
@@ -218,6 +890,328 @@ impl VocDemoD {
             mode: Mode::None,
             tongue_pos: 0.0,
             tongue_diam: 0.0,
+        })
+    }
+}
+
+/// A seeded voice at a speaking pitch, shared by the tests.
+#[cfg(test)]
+pub(crate) fn test_voc() -> Voc {
+    Voc::from_config(
+        VocConfig::builder()
+            .frequency(160.0)
+            .seed(1)
+            .build()
+            .unwrap(),
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automation::Curve;
+
+    fn render(voc: &mut Voc, chunks: usize) -> Vec<f64> {
+        (0..chunks).flat_map(|_| voc.step().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_new_validates() {
+        let v = Voc::new(44100.0, 512, 0.125, 140.0, 0.6, 44, 28, 17, 32, 12, 6, 39);
+        assert!(v.is_ok());
+        assert!(matches!(
+            Voc::new(44100.0, 512, 0.125, 140.0, 0.6, 44, 28, 17, 12, 12, 6, 39),
+            Err(ConfigError::Misordered { .. })
+        ));
+        assert!(VocDemoD::new(0.0, 512).is_err());
+    }
+
+    #[test]
+    fn test_pitch_marks_follow_frequency() {
+        let mut v = test_voc();
+        v.set_pitch_wobble(0.0);
+        v.set_vibrato_depth(0.0);
+        v.set_frequency(100.0);
+
+        let mut starts = Vec::new();
+        let mut closures = 0;
+        for block in 0..20 {
+            v.step();
+            for mark in v.pitch_marks() {
+                match mark.kind {
+                    PitchMarkKind::PeriodStart => starts.push(block * 512 + mark.index),
+                    PitchMarkKind::GlottalClosure => closures += 1,
+                }
+            }
         }
+
+        assert!((closures as i64 - starts.len() as i64).abs() <= 1);
+        for w in starts.windows(2) {
+            assert!((w[1] - w[0]).abs_diff(441) <= 1);
+        }
+    }
+
+    #[test]
+    fn test_long_blocks_keep_pitch_marks_preallocated() {
+        let mut v = Voc::from_config(VocConfig::builder().chunk(64).build().unwrap()).unwrap();
+        v.set_frequency(400.0);
+        let capacity = v.marks.capacity();
+        let mut out = vec![0.0; 4096];
+        v.process(&mut out);
+        assert_eq!(v.pitch_marks().len(), 64);
+        assert!(v.dropped_pitch_marks() > 0);
+        assert_eq!(v.marks.capacity(), capacity);
+
+        v.set_max_pitch_marks(256);
+        let capacity = v.marks.capacity();
+        v.process(&mut out);
+        assert!(v.pitch_marks().len() > 64);
+        assert_eq!(v.dropped_pitch_marks(), 0);
+        assert_eq!(v.marks.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_articulation_speed_ignores_chunk_size() {
+        let mut small = Voc::from_config(VocConfig::builder().chunk(256).build().unwrap()).unwrap();
+        let mut large =
+            Voc::from_config(VocConfig::builder().chunk(1024).build().unwrap()).unwrap();
+        small.set_movement_speed(5.0);
+        large.set_movement_speed(5.0);
+        small.set_tract_diameters(39..44, vec![0.0; 5]);
+        large.set_tract_diameters(39..44, vec![0.0; 5]);
+
+        for _ in 0..8 {
+            small.step();
+        }
+        for _ in 0..2 {
+            large.step();
+        }
+
+        let (a, b) = (
+            small.current_tract_diameters()[40],
+            large.current_tract_diameters()[40],
+        );
+        assert!((a - b).abs() < 1e-9);
+        assert!(a < 1.5);
+    }
+
+    #[test]
+    fn test_reshape_interval_decouples_output_from_chunk() {
+        let render_with_chunk = |chunk: usize| {
+            let config = VocConfig::builder().chunk(chunk).seed(3).build().unwrap();
+            let mut v = Voc::from_config(config).unwrap();
+            v.set_reshape_interval(16);
+            v.set_tract_diameters(39..44, vec![0.0; 5]);
+            render(&mut v, 1024 / chunk)
+        };
+
+        assert_eq!(render_with_chunk(64), render_with_chunk(512));
+    }
+
+    #[test]
+    fn test_process_matches_step_for_any_block_size() {
+        let config = VocConfig::builder().seed(11).build().unwrap();
+        let mut a = Voc::from_config(config.clone()).unwrap();
+        let expected = render(&mut a, 4);
+
+        let mut b = Voc::from_config(config).unwrap();
+        let mut out = vec![0.0; expected.len()];
+        let mut start = 0;
+        for len in [1, 100, 511, 37, 1000, 399].iter().cycle() {
+            let end = (start + len).min(out.len());
+            b.process(&mut out[start..end]);
+            start = end;
+            if start == out.len() {
+                break;
+            }
+        }
+
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_frames_split_the_mono_output() {
+        let config = VocConfig::builder().seed(5).build().unwrap();
+        let mut mono = vec![0.0; 2048];
+        Voc::from_config(config.clone()).unwrap().process(&mut mono);
+
+        let mut v = Voc::from_config(config).unwrap();
+        let mut frames = vec![Frame::default(); 2048];
+        v.process_frames(&mut frames);
+        assert_eq!(frames.iter().map(Frame::mono).collect::<Vec<_>>(), mono);
+        assert!(frames.iter().any(|f| f.glottis != 0.0));
+        assert!(frames.iter().all(|f| f.noise == 0.0));
+
+        v.set_tract_diameters(30..31, vec![0.4]);
+        v.process_frames(&mut frames);
+        assert!(frames.iter().any(|f| f.noise != 0.0));
+    }
+
+    #[test]
+    fn test_automation_lands_on_exact_sample() {
+        let mut v = test_voc();
+        v.set_reshape_interval(1);
+        v.set_movement_speed(1.0e6);
+        v.automation_mut().set(Param::Diameter(40), 1000, 0.0);
+
+        let mut out = vec![0.0; 1000];
+        v.process(&mut out);
+        assert_eq!(v.current_tract_diameters()[40], 1.5);
+        v.process(&mut out[..1]);
+        assert_eq!(v.current_tract_diameters()[40], 0.0);
+    }
+
+    #[test]
+    fn test_intensity_step_lands_on_exact_sample() {
+        let mut v = test_voc();
+        v.automation_mut().set(Param::Intensity, 0, 1.0);
+        v.automation_mut().set(Param::Intensity, 1000, 0.0);
+
+        let mut frames = vec![Frame::default(); 2048];
+        v.process_frames(&mut frames);
+        assert!(frames[1000 - 64..1000].iter().any(|f| f.glottis != 0.0));
+        assert!(frames[1000..].iter().all(|f| f.glottis == 0.0));
+    }
+
+    #[test]
+    fn test_frequency_automation_follows_ramp() {
+        let mut v = test_voc();
+        v.set_pitch_wobble(0.0);
+        v.set_vibrato_depth(0.0);
+        let a = v.automation_mut();
+        a.set(Param::Frequency, 0, 100.0);
+        a.ramp(Param::Frequency, 44100, 200.0, Curve::Linear);
+
+        let mut out = vec![0.0; 44100];
+        v.process(&mut out);
+        let starts: Vec<usize> = v
+            .pitch_marks()
+            .iter()
+            .filter(|m| m.kind == PitchMarkKind::PeriodStart)
+            .map(|m| m.index)
+            .collect();
+        for w in starts.windows(2) {
+            let expected = 44100.0 / (100.0 + 100.0 * w[0] as f64 / 44100.0);
+            assert!(((w[1] - w[0]) as f64 - expected).abs() < 2.0);
+        }
+    }
+
+    #[test]
+    fn test_setters_hold_after_automation_ends() {
+        let mut v = test_voc();
+        let a = v.automation_mut();
+        a.set(Param::Frequency, 0, 100.0);
+        a.set(Param::Tenseness, 0, 0.3);
+        a.set(Param::Velum, 0, 0.2);
+        let mut out = vec![0.0; 2048];
+        v.process(&mut out);
+        assert_eq!(v.frequency(), 100.0);
+
+        v.set_frequency(300.0);
+        v.set_tenseness(0.8);
+        v.set_velum(0.01);
+        v.process(&mut out);
+        assert_eq!(v.frequency(), 300.0);
+        assert_eq!(v.tenseness(), 0.8);
+        assert_eq!(v.velum(), 0.01);
+    }
+
+    #[test]
+    fn test_same_seed_renders_identically() {
+        let mut a = test_voc();
+        let mut b = test_voc();
+        a.set_seed(7);
+        b.set_seed(7);
+        a.set_tenseness(0.3);
+        b.set_tenseness(0.3);
+        let out = render(&mut a, 20);
+        assert_eq!(out, render(&mut b, 20));
+
+        let mut c = test_voc();
+        c.set_seed(8);
+        c.set_tenseness(0.3);
+        assert_ne!(out, render(&mut c, 20));
+    }
+
+    #[test]
+    fn test_f32_stays_close_to_f64() {
+        // A vowel, a closure at the alveolar ridge and its release into a
+        // fricative, so the transients and turbulence noise are covered.
+        fn articulate<F: Float>(v: &mut Voc<F>, tongue: f64, constriction: f64) {
+            v.tongue_shape(tongue, 2.5);
+            v.constrict(37.0, constriction);
+        }
+
+        let config = VocConfig::builder().seed(5).build().unwrap();
+        let mut a = Voc::from_config(config.clone()).unwrap();
+        let mut b = Voc::<f32>::with_config(config).unwrap();
+        let mut wide = vec![0.0; 11025];
+        let mut narrow = vec![0.0f32; 11025];
+        for (tongue, constriction) in [(20.0, 3.0), (30.0, 0.0), (30.0, 0.4)] {
+            articulate(&mut a, tongue, constriction);
+            articulate(&mut b, tongue, constriction);
+            a.process(&mut wide);
+            b.process(&mut narrow);
+
+            let peak = wide.iter().fold(0.0f64, |m, x| m.max(x.abs()));
+            let error = wide
+                .iter()
+                .zip(&narrow)
+                .fold(0.0f64, |m, (x, &y)| m.max((x - y as f64).abs()));
+            assert!(error < peak * 1e-3, "error {} for peak {}", error, peak);
+        }
+    }
+
+    #[test]
+    fn test_f32_follows_a_pitch_glide() {
+        let config = VocConfig::builder().seed(6).build().unwrap();
+        let mut a = Voc::from_config(config.clone()).unwrap();
+        let mut b = Voc::<f32>::with_config(config).unwrap();
+        for v in [a.automation_mut(), b.automation_mut()] {
+            v.set(Param::Frequency, 0, 100.0);
+            v.ramp(Param::Frequency, 22050, 300.0, Curve::Exponential);
+        }
+
+        let mut wide = vec![0.0; 22050];
+        let mut narrow = vec![0.0f32; 22050];
+        a.process(&mut wide);
+        b.process(&mut narrow);
+        assert!(a.pitch_marks().len() > 100);
+        assert_eq!(a.pitch_marks(), b.pitch_marks());
+
+        let peak = wide.iter().fold(0.0f64, |m, x| m.max(x.abs()));
+        let error = wide
+            .iter()
+            .zip(&narrow)
+            .fold(0.0f64, |m, (x, &y)| m.max((x - y as f64).abs()));
+        assert!(error < peak * 1e-3, "error {} for peak {}", error, peak);
+    }
+
+    #[test]
+    fn test_restored_snapshot_continues_the_render() {
+        let mut a = test_voc();
+        a.set_seed(3);
+        a.set_frequency(120.0);
+        a.tongue_shape(25.0, 2.0);
+        render(&mut a, 5);
+        // A closure mid-way leaves transients and reflections in flight.
+        a.set_tract_diameter(36, 0.0);
+        a.process(&mut [0.0; 300]);
+        a.set_tract_diameter(36, 1.5);
+        let snapshot = a.snapshot();
+        assert_eq!(snapshot.sample_time(), a.sample_time());
+
+        let mut b =
+            Voc::from_config(VocConfig::builder().n(60).lip_start(53).build().unwrap()).unwrap();
+        b.restore(&snapshot);
+        assert_eq!(b.tract_size(), a.tract_size());
+        let mut c = test_voc();
+        c.restore(&snapshot);
+        c.set_frequency(200.0);
+
+        let ending = render(&mut a, 10);
+        assert_eq!(render(&mut b, 10), ending);
+        assert_ne!(render(&mut c, 10), ending);
     }
 }